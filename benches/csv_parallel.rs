@@ -0,0 +1,56 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rcli::{process_csv, CsvConvertOpts, CsvReaderOpts, InferMode, OutputFormat};
+use std::{fmt::Write, fs};
+
+// about 27 MiB of mixed numeric, text and quoted multi-line fields
+fn sample_csv(rows: usize) -> String {
+    let mut data = String::from("id,name,score,active,note\n");
+    for i in 0..rows {
+        writeln!(
+            data,
+            "{},player {},{}.{},{},\"line one\nline two, {}\"",
+            i,
+            i % 977,
+            i % 100,
+            i % 10,
+            i % 2 == 0,
+            i
+        )
+        .unwrap();
+    }
+    data
+}
+
+fn bench_threads(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.csv");
+    let output = dir.path().join("output.json");
+    let data = sample_csv(500_000);
+    fs::write(&input, &data).unwrap();
+    let input = input.to_str().unwrap();
+    let opts = CsvReaderOpts::default();
+
+    let mut group = c.benchmark_group("csv_to_ndjson");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Bytes(data.len() as u64));
+    for threads in [1, 2, 4, 0] {
+        let convert_opts = CsvConvertOpts {
+            threads,
+            // column inference adds a serial scan of the file
+            infer: InferMode::Cell,
+            stream: true,
+            ..Default::default()
+        };
+        group.bench_with_input(BenchmarkId::new("threads", threads), &threads, |b, _| {
+            b.iter(|| {
+                let output = output.to_string_lossy().into_owned();
+                process_csv(input, output, OutputFormat::Ndjson, &opts, &convert_opts).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_threads);
+criterion_main!(benches);
@@ -1,45 +1,755 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{ArgAction, ArgGroup, Args, Parser};
 use core::fmt;
-use std::str::FromStr;
+use encoding_rs::Encoding;
+use enum_dispatch::enum_dispatch;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use crate::{process_csv, CmdExecutor};
+use crate::{
+    diff_to_json_patch, get_content, get_reader, get_writer, load_schema, load_validation_spec,
+    process_csv, process_csv_cat, process_csv_diff, process_csv_from, process_csv_join,
+    process_csv_query, process_csv_show, process_csv_split, process_csv_stats,
+    process_csv_validate, render_diff_report, render_stats_table, Aggregate, BadRowPolicy,
+    CmdExecutor, ColumnSelection, CsvConvertOpts, CsvReaderOpts, Filter, JoinSpec, MaskRule,
+    SortKey, SplitMode, TableOpts,
+};
 
 use super::parse_input_file;
 
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {
     Json,
+    Ndjson,
     Yaml,
     Toml,
+    Msgpack,
+    Cbor,
+    Parquet,
+    Sql,
+    Sqlite,
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum InputFormat {
+    Json,
+    Ndjson,
+    Yaml,
+    Toml,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StatsFormat {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DiffFormat {
+    Report,
+    JsonPatch,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InferMode {
+    None,
+    Cell,
+    #[default]
+    Column,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputEncoding {
+    #[default]
+    Auto,
+    Label(&'static Encoding),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JoinKind {
+    #[default]
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SqlDialect {
+    #[default]
+    Postgres,
+    Mysql,
+    Sqlite,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TomlNullPolicy {
+    #[default]
+    Omit,
+    Empty,
 }
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = parse_input_file)]
-    pub input: String,
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+
+    /// Input CSV file, `-` for stdin
+    #[arg(short, long, value_parser = parse_input_file, required = true)]
+    pub input: Option<String>,
 
+    /// Output file, `-` for stdout [default: output.<format>]
     #[arg(short, long)]
     pub output: Option<String>,
 
     #[arg(long, value_parser = parse_format , default_value = "json")]
     pub format: OutputFormat,
 
-    #[arg(short, long, default_value_t = ',')]
-    pub delimiter: char,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+
+    /// Value type inference: none, cell (each value on its own) or column (one type per column)
+    #[arg(long, value_parser = parse_infer_mode, default_value = "column")]
+    pub infer: InferMode,
+
+    /// YAML/JSON file mapping column names to types, overriding inference
+    #[arg(long, value_parser = parse_input_file)]
+    pub schema: Option<String>,
+
+    /// Key of the array of tables holding the rows in TOML output
+    #[arg(long, default_value = "rows")]
+    pub toml_root: String,
+
+    /// How TOML output represents null cells: omit the key or write an empty string
+    #[arg(long, value_parser = parse_toml_null_policy, default_value = "omit")]
+    pub toml_null: TomlNullPolicy,
+
+    /// Table created by sql and sqlite output [default: input file name]
+    #[arg(long)]
+    pub table: Option<String>,
+
+    /// SQL dialect of sql output: postgres, mysql or sqlite
+    #[arg(long, value_parser = parse_sql_dialect, default_value = "postgres")]
+    pub sql_dialect: SqlDialect,
+
+    /// Rows per INSERT in sql output; the first batch also decides the column types
+    #[arg(long, default_value_t = 500)]
+    pub batch_size: usize,
+
+    /// Write html output as a bare <table> to embed in another page
+    #[arg(long)]
+    pub html_fragment: bool,
+
+    /// Convert record by record with bounded memory (always on for inputs over 64 MiB)
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Threads parsing the input, 0 for one per core; bad rows are always skipped on one thread
+    #[arg(long, default_value_t = 1)]
+    pub threads: usize,
+
+    /// Only keep these columns, in this order (header names or 1-based indexes)
+    #[arg(long, value_delimiter = ',')]
+    pub select: Vec<String>,
+
+    /// Drop these columns (header names or 1-based indexes)
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Rename columns, e.g. `--rename "Kit Number=number"`
+    #[arg(long, value_delimiter = ',', value_parser = parse_rename)]
+    pub rename: Vec<(String, String)>,
+
+    /// Move these columns to the front, keeping the remaining ones in input order
+    #[arg(long, value_delimiter = ',')]
+    pub order: Vec<String>,
+
+    /// Only keep rows matching the expression, e.g. `Age > 30 and Position == "Defender"`
+    #[arg(long = "where", value_parser = parse_filter)]
+    pub filter: Option<Filter>,
+
+    /// Drop rows repeating the values of these columns, keeping the first one
+    #[arg(long, value_delimiter = ',')]
+    pub dedupe_by: Vec<String>,
+
+    /// Emit one row per distinct value of these columns, with the `--agg` aggregations
+    #[arg(long, value_delimiter = ',')]
+    pub group_by: Vec<String>,
+
+    /// Aggregations per group: count, sum:col, avg:col, min:col or max:col [default: count]
+    #[arg(long, value_delimiter = ',', value_parser = parse_aggregate, requires = "group_by")]
+    pub agg: Vec<Aggregate>,
+
+    /// Sort rows by these columns, e.g. `--sort-by Age:desc,Name`; spills to disk when large
+    #[arg(long, value_delimiter = ',', value_parser = parse_sort_key)]
+    pub sort_by: Vec<SortKey>,
+
+    /// Mask columns, e.g. `--mask card=partial:4,email=fake:email`; strategies are redact,
+    /// hash, partial[:N] and fake:name|email|phone|number
+    #[arg(long, value_delimiter = ',', value_parser = parse_mask_rule)]
+    pub mask: Vec<MaskRule>,
+
+    /// Blake3 key file, required by the hash and fake strategies
+    #[arg(long, value_parser = parse_input_file)]
+    pub mask_key: Option<String>,
+
+    /// Build nested objects from `address.city` and arrays from `tags[0]` style headers
+    #[arg(long)]
+    pub nest: bool,
+
+    #[command(flatten)]
+    pub bad_rows: BadRowArgs,
 
-    #[arg(long, default_value_t = true)]
+    /// Print the first rows as a table instead of writing an output file
+    #[arg(long, conflicts_with_all = ["output", "format"])]
+    pub show: bool,
+
+    /// Number of rows printed by `--show`
+    #[arg(long, default_value_t = 20, requires = "show")]
+    pub limit: usize,
+
+    /// Cells wider than this are truncated in `--show` tables
+    #[arg(long, default_value_t = 30, requires = "show")]
+    pub max_width: usize,
+
+    /// Color the `--show` table header: auto, always or never
+    #[arg(long, value_parser = parse_color_mode, default_value = "auto", requires = "show")]
+    pub color: ColorMode,
+}
+
+// CSV parsing options shared by every csv subcommand
+#[derive(Debug, Args)]
+pub struct CsvReadArgs {
+    #[arg(short, long, value_parser = parse_csv_byte, default_value = ",")]
+    pub delimiter: u8,
+
+    #[arg(long, value_parser = parse_csv_byte, default_value = "\"")]
+    pub quote: u8,
+
+    #[arg(long, value_parser = parse_csv_byte)]
+    pub escape: Option<u8>,
+
+    #[arg(long, value_parser = parse_csv_byte)]
+    pub comment: Option<u8>,
+
+    /// Allow records with a varying number of fields
+    #[arg(long)]
+    pub flexible: bool,
+
+    /// Trim leading and trailing whitespace of headers and fields
+    #[arg(long)]
+    pub trim: bool,
+
+    /// Treat the first row as header; with `--header false` keys are col_1..col_n
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
     pub header: bool,
+
+    /// Input encoding: auto (BOM, then a guess) or a label such as utf-16le, gbk or shift_jis
+    #[arg(long, value_parser = parse_encoding, default_value = "auto")]
+    pub encoding: InputEncoding,
+
+    /// Sheet of an .xlsx or .ods input, by name or 1-based index [default: the first one]
+    #[arg(long)]
+    pub sheet: Option<String>,
+}
+
+// Handling of malformed records, shared by the csv subcommands that copy records
+#[derive(Debug, Args)]
+pub struct BadRowArgs {
+    /// Skip malformed records instead of failing, reporting them on stderr
+    #[arg(long)]
+    pub skip_bad_rows: bool,
+
+    /// Skip malformed records and copy them, after the header, to this CSV file
+    #[arg(long)]
+    pub bad_rows_out: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum CsvSubCommand {
+    #[command(about = "Convert JSON, NDJSON, YAML or TOML back to CSV")]
+    From(CsvFromOpts),
+    #[command(about = "Show per-column statistics of a CSV file")]
+    Stats(CsvStatsOpts),
+    #[command(about = "Join two CSV files on key columns")]
+    Join(CsvJoinOpts),
+    #[command(about = "Check a CSV file against a column spec or JSON Schema")]
+    Validate(CsvValidateOpts),
+    #[command(about = "Split a CSV file by rows, size or column value")]
+    Split(CsvSplitOpts),
+    #[command(about = "Concatenate CSV files, aligning columns by name")]
+    Cat(CsvCatOpts),
+    #[command(about = "Run a SQL query over CSV files")]
+    Query(CsvQueryOpts),
+    #[command(about = "Show rows added, removed and changed between two CSV files")]
+    Diff(CsvDiffOpts),
+}
+
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("mode").required(true)))]
+pub struct CsvSplitOpts {
+    #[arg(short, long, value_parser = parse_input_file, default_value = "-")]
+    pub input: String,
+
+    /// Records per file
+    #[arg(long, group = "mode")]
+    pub rows: Option<usize>,
+
+    /// Approximate size per file, e.g. 500K or 10M
+    #[arg(long, value_parser = parse_byte_size, group = "mode")]
+    pub bytes: Option<u64>,
+
+    /// Write one file per distinct value of this column (header name or 1-based index)
+    #[arg(long, group = "mode")]
+    pub by: Option<String>,
+
+    /// Directory the files are written to, created when missing
+    #[arg(long, default_value = ".")]
+    pub out_dir: PathBuf,
+
+    /// File name prefix [default: input file name, or `split` for stdin]
+    #[arg(long)]
+    pub prefix: Option<String>,
+
+    /// Overwrite existing output files
+    #[arg(long)]
+    pub force: bool,
+
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+
+    #[command(flatten)]
+    pub bad_rows: BadRowArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvCatOpts {
+    /// CSV files to concatenate, `-` for stdin
+    #[arg(value_parser = parse_input_file, required = true)]
+    pub inputs: Vec<String>,
+
+    /// Output CSV file, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Allow files with different columns, leaving missing cells empty
+    #[arg(long)]
+    pub union: bool,
+
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+
+    #[command(flatten)]
+    pub bad_rows: BadRowArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvFromOpts {
+    #[arg(short, long, value_parser = parse_input_file, default_value = "-")]
+    pub input: String,
+
+    /// Output CSV file, `-` for stdout
+    #[arg(short, long, default_value = "output.csv")]
+    pub output: String,
+
+    /// Input format, detected from the input file extension when omitted
+    #[arg(long, value_parser = parse_input_format)]
+    pub format: Option<InputFormat>,
+
+    #[arg(short, long, value_parser = parse_csv_byte, default_value = ",")]
+    pub delimiter: u8,
+
+    /// Key of the array of tables holding the rows in TOML input
+    #[arg(long, default_value = "rows")]
+    pub toml_root: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvStatsOpts {
+    #[arg(short, long, value_parser = parse_input_file, default_value = "-")]
+    pub input: String,
+
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+
+    #[arg(long, value_parser = parse_stats_format, default_value = "table")]
+    pub format: StatsFormat,
+
+    /// Number of most frequent values to show per column
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvJoinOpts {
+    /// Left CSV file, `-` for stdin
+    #[arg(value_parser = parse_input_file)]
+    pub left: String,
+
+    /// Right CSV file, `-` for stdin
+    #[arg(value_parser = parse_input_file)]
+    pub right: String,
+
+    /// Key columns of the left file, and of the right one unless `--right-on` is given
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub on: Vec<String>,
+
+    /// Key columns of the right file
+    #[arg(long, value_delimiter = ',')]
+    pub right_on: Vec<String>,
+
+    /// Join type: inner, left, right or full
+    #[arg(long, value_parser = parse_join_kind, default_value = "inner")]
+    pub how: JoinKind,
+
+    /// Suffix for left columns whose name also appears in the right file
+    #[arg(long, default_value = "_left")]
+    pub left_suffix: String,
+
+    /// Suffix for right columns whose name also appears in the left file
+    #[arg(long, default_value = "_right")]
+    pub right_suffix: String,
+
+    /// Output file, `-` for stdout [default: output.<format>]
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long, value_parser = parse_format, default_value = "json")]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+
+    #[arg(long, value_parser = parse_infer_mode, default_value = "column")]
+    pub infer: InferMode,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOpts {
+    #[arg(short, long, value_parser = parse_input_file, default_value = "-")]
+    pub input: String,
+
+    /// YAML/JSON column spec, or a JSON Schema describing one row
+    #[arg(long, value_parser = parse_input_file)]
+    pub schema: String,
+
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvQueryOpts {
+    /// SQLite flavored SQL, e.g. `SELECT Position, count(*) FROM players GROUP BY Position`
+    pub sql: String,
+
+    /// Table to query as `name=file.csv`, or just the file to name it after the file
+    #[arg(long, value_parser = parse_query_table, required = true)]
+    pub table: Vec<(String, String)>,
+
+    /// Output file, `-` for stdout [default: output.<format>]
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long, value_parser = parse_format, default_value = "json")]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+
+    #[arg(long, value_parser = parse_infer_mode, default_value = "column")]
+    pub infer: InferMode,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDiffOpts {
+    /// Old version of the file, `-` for stdin
+    #[arg(value_parser = parse_input_file)]
+    pub old: String,
+
+    /// New version of the file, `-` for stdin
+    #[arg(value_parser = parse_input_file)]
+    pub new: String,
+
+    /// Columns identifying a row in both versions
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub key: Vec<String>,
+
+    /// Output format: report or json-patch
+    #[arg(long, value_parser = parse_diff_format, default_value = "report")]
+    pub format: DiffFormat,
+
+    /// Output file, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+}
+
+impl CsvReadArgs {
+    pub fn reader_opts(&self) -> CsvReaderOpts {
+        CsvReaderOpts {
+            delimiter: self.delimiter,
+            quote: self.quote,
+            escape: self.escape,
+            comment: self.comment,
+            flexible: self.flexible,
+            trim: self.trim,
+            has_headers: self.header,
+            encoding: self.encoding,
+            sheet: self.sheet.clone(),
+        }
+    }
+}
+
+impl BadRowArgs {
+    pub fn policy(&self) -> BadRowPolicy {
+        match &self.bad_rows_out {
+            Some(path) => BadRowPolicy::SkipTo(path.clone()),
+            None if self.skip_bad_rows => BadRowPolicy::Skip,
+            None => BadRowPolicy::Fail,
+        }
+    }
+}
+
+impl CsvOpts {
+    pub fn convert_opts(&self) -> Result<CsvConvertOpts> {
+        let schema = match &self.schema {
+            Some(path) => load_schema(path)?,
+            None => Default::default(),
+        };
+        Ok(CsvConvertOpts {
+            infer: self.infer,
+            schema,
+            toml_root: self.toml_root.clone(),
+            toml_null: self.toml_null,
+            stream: self.stream,
+            columns: ColumnSelection {
+                select: self.select.clone(),
+                exclude: self.exclude.clone(),
+                rename: self.rename.clone(),
+                order: self.order.clone(),
+            },
+            filter: self.filter.clone(),
+            dedupe: self.dedupe_by.clone(),
+            group_by: self.group_by.clone(),
+            aggregates: self.agg.clone(),
+            sort: self.sort_by.clone(),
+            masks: self.mask.clone(),
+            mask_key: self.mask_key.as_deref().map(get_content).transpose()?,
+            nest: self.nest,
+            sql_table: match &self.table {
+                Some(table) => table.clone(),
+                None => self
+                    .input
+                    .as_deref()
+                    .filter(|input| *input != "-")
+                    .and_then(|input| Path::new(input).file_stem())
+                    .map_or_else(|| "data".to_string(), |s| s.to_string_lossy().into_owned()),
+            },
+            sql_dialect: self.sql_dialect,
+            sql_batch_size: self.batch_size,
+            html_fragment: self.html_fragment,
+            threads: self.threads,
+            bad_rows: self.bad_rows.policy(),
+        })
+    }
 }
 
 impl CmdExecutor for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        let opts = self.read.reader_opts();
+        let convert_opts = self.convert_opts()?;
+        let input = self.input.expect("input is required without a subcommand");
+        if self.show {
+            let table_opts = TableOpts {
+                limit: self.limit,
+                max_width: self.max_width,
+                color: self.color,
+            };
+            return process_csv_show(&input, &opts, &convert_opts, table_opts);
+        }
         let output = if let Some(o) = self.output {
             o.clone()
         } else {
             format!("output.{}", self.format)
         };
-        process_csv(&self.input, output, self.format)
+        process_csv(&input, output, self.format, &opts, &convert_opts)
+    }
+}
+
+impl CmdExecutor for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = self.read.reader_opts();
+        let mut reader = opts.open(&self.input)?;
+        let stats = process_csv_stats(&mut reader, &opts, self.top)?;
+        match self.format {
+            StatsFormat::Table => print!("{}", render_stats_table(&stats)),
+            StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = self.read.reader_opts();
+        let spec = JoinSpec {
+            kind: self.how,
+            left_on: self.on,
+            right_on: self.right_on,
+            left_suffix: self.left_suffix,
+            right_suffix: self.right_suffix,
+        };
+        let convert_opts = CsvConvertOpts {
+            infer: self.infer,
+            ..Default::default()
+        };
+        let output = self
+            .output
+            .unwrap_or_else(|| format!("output.{}", self.format));
+        process_csv_join(
+            &self.left,
+            &self.right,
+            &output,
+            self.format,
+            &opts,
+            &spec,
+            &convert_opts,
+        )
+    }
+}
+
+impl CmdExecutor for CsvQueryOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = self.read.reader_opts();
+        let convert_opts = CsvConvertOpts {
+            infer: self.infer,
+            ..Default::default()
+        };
+        let output = self
+            .output
+            .unwrap_or_else(|| format!("output.{}", self.format));
+        process_csv_query(
+            &self.sql,
+            &self.table,
+            &output,
+            self.format,
+            &opts,
+            &convert_opts,
+        )
+    }
+}
+
+impl CmdExecutor for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = self.read.reader_opts();
+        let diff = process_csv_diff(&self.old, &self.new, &self.key, &opts)?;
+        let content = match self.format {
+            DiffFormat::Report => render_diff_report(&diff, &self.old, &self.new),
+            DiffFormat::JsonPatch => {
+                serde_json::to_string_pretty(&diff_to_json_patch(&diff))? + "\n"
+            }
+        };
+        let mut writer = get_writer(&self.output)?;
+        writer.write_all(content.as_bytes())?;
+        Ok(writer.flush()?)
+    }
+}
+
+impl CmdExecutor for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let spec = load_validation_spec(&self.schema)?;
+        let opts = self.read.reader_opts();
+        let violations = process_csv_validate(opts.open(&self.input)?, &opts, &spec)?;
+        for violation in &violations {
+            println!("{}", violation);
+        }
+        if !violations.is_empty() {
+            anyhow::bail!("{} violations found", violations.len());
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = self.read.reader_opts();
+        let mode = match (self.rows, self.bytes, self.by) {
+            (Some(rows), _, _) => SplitMode::Rows(rows),
+            (_, Some(bytes), _) => SplitMode::Bytes(bytes),
+            (_, _, Some(column)) => SplitMode::Column(column),
+            _ => unreachable!("clap requires one split mode"),
+        };
+        let prefix = match self.prefix {
+            Some(prefix) => prefix,
+            None => Path::new(&self.input)
+                .file_stem()
+                .filter(|_| self.input != "-")
+                .map_or_else(|| "split".to_string(), |s| s.to_string_lossy().into_owned()),
+        };
+        let paths = process_csv_split(
+            &self.input,
+            &self.out_dir,
+            &prefix,
+            &mode,
+            &opts,
+            self.force,
+            &self.bad_rows.policy(),
+        )?;
+        for path in paths {
+            println!("{}", path.display());
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvCatOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = self.read.reader_opts();
+        process_csv_cat(
+            &self.inputs,
+            &self.output,
+            &opts,
+            self.union,
+            &self.bad_rows.policy(),
+        )
+    }
+}
+
+impl CmdExecutor for CsvFromOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let format = match self.format {
+            Some(format) => format,
+            None => InputFormat::from_path(&self.input)?,
+        };
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = get_writer(&self.output)?;
+        process_csv_from(
+            &mut reader,
+            &mut writer,
+            format,
+            self.delimiter,
+            &self.toml_root,
+        )
     }
 }
 
@@ -47,12 +757,134 @@ fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
     format.parse()
 }
 
+fn parse_input_format(format: &str) -> Result<InputFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_stats_format(format: &str) -> Result<StatsFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_diff_format(format: &str) -> Result<DiffFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_infer_mode(mode: &str) -> Result<InferMode, anyhow::Error> {
+    mode.parse()
+}
+
+fn parse_encoding(encoding: &str) -> Result<InputEncoding, anyhow::Error> {
+    encoding.parse()
+}
+
+fn parse_join_kind(kind: &str) -> Result<JoinKind, anyhow::Error> {
+    kind.parse()
+}
+
+fn parse_sql_dialect(dialect: &str) -> Result<SqlDialect, anyhow::Error> {
+    dialect.parse()
+}
+
+fn parse_color_mode(mode: &str) -> Result<ColorMode, anyhow::Error> {
+    mode.parse()
+}
+
+fn parse_toml_null_policy(policy: &str) -> Result<TomlNullPolicy, anyhow::Error> {
+    policy.parse()
+}
+
+fn parse_filter(filter: &str) -> Result<Filter, anyhow::Error> {
+    filter.parse()
+}
+
+fn parse_aggregate(agg: &str) -> Result<Aggregate, anyhow::Error> {
+    agg.parse()
+}
+
+fn parse_sort_key(key: &str) -> Result<SortKey, anyhow::Error> {
+    key.parse()
+}
+
+fn parse_mask_rule(rule: &str) -> Result<MaskRule, anyhow::Error> {
+    rule.parse()
+}
+
+fn parse_rename(rename: &str) -> Result<(String, String), &'static str> {
+    match rename.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => Ok((old.into(), new.into())),
+        _ => Err("Expected old=new"),
+    }
+}
+
+fn parse_byte_size(size: &str) -> Result<u64, &'static str> {
+    let size = size.trim();
+    let (n, unit) = size.split_at(
+        size.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(size.len()),
+    );
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        _ => return Err("Expected a size such as 500K or 10M"),
+    };
+    n.parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .filter(|n| *n > 0)
+        .ok_or("Expected a size such as 500K or 10M")
+}
+
+fn parse_query_table(table: &str) -> Result<(String, String), &'static str> {
+    let (name, path) = match table.split_once('=') {
+        Some((name, path)) if !name.is_empty() => (name.to_string(), path),
+        Some(_) => return Err("Expected name=file.csv"),
+        None => {
+            let name = Path::new(table)
+                .file_stem()
+                .filter(|_| table != "-")
+                .ok_or("Name stdin tables, e.g. data=-")?;
+            (name.to_string_lossy().into_owned(), table)
+        }
+    };
+    Ok((name, parse_input_file(path)?))
+}
+
+fn parse_csv_byte(s: &str) -> Result<u8, &'static str> {
+    // accept escaped/named forms for characters that are awkward to type in a shell
+    let c = match s {
+        "\\t" | "tab" => '\t',
+        "space" => ' ',
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err("Expected a single character"),
+            }
+        }
+    };
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err("Only ASCII characters are supported")
+    }
+}
+
 impl From<OutputFormat> for &'static str {
     fn from(format: OutputFormat) -> Self {
         match format {
             OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
             OutputFormat::Yaml => "yaml",
             OutputFormat::Toml => "toml",
+            OutputFormat::Msgpack => "msgpack",
+            OutputFormat::Cbor => "cbor",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Sql => "sql",
+            OutputFormat::Sqlite => "sqlite",
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Html => "html",
         }
     }
 }
@@ -62,8 +894,16 @@ impl FromStr for OutputFormat {
     fn from_str(s: &str) -> std::prelude::v1::Result<Self, Self::Err> {
         match s {
             "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
             "yaml" => Ok(OutputFormat::Yaml),
             "toml" => Ok(OutputFormat::Toml),
+            "msgpack" => Ok(OutputFormat::Msgpack),
+            "cbor" => Ok(OutputFormat::Cbor),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "sql" => Ok(OutputFormat::Sql),
+            "sqlite" => Ok(OutputFormat::Sqlite),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
             v => anyhow::bail!("Unsupported format: {}", v),
         }
     }
@@ -74,3 +914,302 @@ impl fmt::Display for OutputFormat {
         write!(f, "{}", Into::<&str>::into(*self))
     }
 }
+
+impl InputFormat {
+    pub fn from_path(path: &str) -> Result<Self> {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("yml") => Ok(InputFormat::Yaml),
+            Some("jsonl") => Ok(InputFormat::Ndjson),
+            Some(ext) => ext.parse(),
+            None => anyhow::bail!("Cannot detect the format of {}, please use --format", path),
+        }
+    }
+}
+
+impl From<InputFormat> for &'static str {
+    fn from(format: InputFormat) -> Self {
+        match format {
+            InputFormat::Json => "json",
+            InputFormat::Ndjson => "ndjson",
+            InputFormat::Yaml => "yaml",
+            InputFormat::Toml => "toml",
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(InputFormat::Json),
+            "ndjson" => Ok(InputFormat::Ndjson),
+            "yaml" => Ok(InputFormat::Yaml),
+            "toml" => Ok(InputFormat::Toml),
+            v => anyhow::bail!("Unsupported format: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<StatsFormat> for &'static str {
+    fn from(format: StatsFormat) -> Self {
+        match format {
+            StatsFormat::Table => "table",
+            StatsFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for StatsFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(StatsFormat::Table),
+            "json" => Ok(StatsFormat::Json),
+            v => anyhow::bail!("Unsupported format: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for StatsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<DiffFormat> for &'static str {
+    fn from(format: DiffFormat) -> Self {
+        match format {
+            DiffFormat::Report => "report",
+            DiffFormat::JsonPatch => "json-patch",
+        }
+    }
+}
+
+impl FromStr for DiffFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "report" => Ok(DiffFormat::Report),
+            "json-patch" => Ok(DiffFormat::JsonPatch),
+            v => anyhow::bail!("Unsupported format: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for DiffFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<InferMode> for &'static str {
+    fn from(mode: InferMode) -> Self {
+        match mode {
+            InferMode::None => "none",
+            InferMode::Cell => "cell",
+            InferMode::Column => "column",
+        }
+    }
+}
+
+impl FromStr for InferMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(InferMode::None),
+            "cell" => Ok(InferMode::Cell),
+            "column" => Ok(InferMode::Column),
+            v => anyhow::bail!("Unsupported infer mode: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for InferMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<InputEncoding> for &'static str {
+    fn from(encoding: InputEncoding) -> Self {
+        match encoding {
+            InputEncoding::Auto => "auto",
+            InputEncoding::Label(encoding) => encoding.name(),
+        }
+    }
+}
+
+impl FromStr for InputEncoding {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(InputEncoding::Auto);
+        }
+        match Encoding::for_label(s.as_bytes()) {
+            Some(encoding) => Ok(InputEncoding::Label(encoding)),
+            None => anyhow::bail!("Unsupported encoding: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for InputEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<JoinKind> for &'static str {
+    fn from(kind: JoinKind) -> Self {
+        match kind {
+            JoinKind::Inner => "inner",
+            JoinKind::Left => "left",
+            JoinKind::Right => "right",
+            JoinKind::Full => "full",
+        }
+    }
+}
+
+impl FromStr for JoinKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inner" => Ok(JoinKind::Inner),
+            "left" => Ok(JoinKind::Left),
+            "right" => Ok(JoinKind::Right),
+            "full" | "outer" => Ok(JoinKind::Full),
+            v => anyhow::bail!("Unsupported join type: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for JoinKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<SqlDialect> for &'static str {
+    fn from(dialect: SqlDialect) -> Self {
+        match dialect {
+            SqlDialect::Postgres => "postgres",
+            SqlDialect::Mysql => "mysql",
+            SqlDialect::Sqlite => "sqlite",
+        }
+    }
+}
+
+impl FromStr for SqlDialect {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" | "postgresql" => Ok(SqlDialect::Postgres),
+            "mysql" => Ok(SqlDialect::Mysql),
+            "sqlite" => Ok(SqlDialect::Sqlite),
+            v => anyhow::bail!("Unsupported sql dialect: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for SqlDialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<ColorMode> for &'static str {
+    fn from(mode: ColorMode) -> Self {
+        match mode {
+            ColorMode::Auto => "auto",
+            ColorMode::Always => "always",
+            ColorMode::Never => "never",
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            v => anyhow::bail!("Unsupported color mode: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl From<TomlNullPolicy> for &'static str {
+    fn from(policy: TomlNullPolicy) -> Self {
+        match policy {
+            TomlNullPolicy::Omit => "omit",
+            TomlNullPolicy::Empty => "empty",
+        }
+    }
+}
+
+impl FromStr for TomlNullPolicy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "omit" => Ok(TomlNullPolicy::Omit),
+            "empty" => Ok(TomlNullPolicy::Empty),
+            v => anyhow::bail!("Unsupported toml null policy: {}", v),
+        }
+    }
+}
+
+impl fmt::Display for TomlNullPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_byte() {
+        assert_eq!(parse_csv_byte(";"), Ok(b';'));
+        assert_eq!(parse_csv_byte("\\t"), Ok(b'\t'));
+        assert_eq!(parse_csv_byte("tab"), Ok(b'\t'));
+        assert_eq!(parse_csv_byte(";;"), Err("Expected a single character"));
+        assert_eq!(
+            parse_csv_byte("é"),
+            Err("Only ASCII characters are supported")
+        );
+    }
+
+    #[test]
+    fn test_parse_rename() {
+        assert_eq!(parse_rename("a=b"), Ok(("a".into(), "b".into())));
+        assert_eq!(parse_rename("a="), Err("Expected old=new"));
+        assert_eq!(parse_rename("ab"), Err("Expected old=new"));
+    }
+
+    #[test]
+    fn test_input_format_from_path() {
+        assert!(matches!(
+            InputFormat::from_path("rows.yml"),
+            Ok(InputFormat::Yaml)
+        ));
+        assert!(matches!(
+            InputFormat::from_path("rows.jsonl"),
+            Ok(InputFormat::Ndjson)
+        ));
+        assert!(InputFormat::from_path("-").is_err());
+        assert!(InputFormat::from_path("rows.csv").is_err());
+    }
+}
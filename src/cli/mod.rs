@@ -18,6 +18,8 @@ pub struct Opts {
     pub cmd: SubCommand,
 }
 
+// parsed once per run, so the size of the csv options does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
 pub enum SubCommand {
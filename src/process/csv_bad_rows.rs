@@ -0,0 +1,213 @@
+use anyhow::Result;
+use csv::{ByteRecord, ErrorKind, Position, Reader, StringRecord, Writer, WriterBuilder};
+use std::{
+    fmt,
+    io::{Read, Write},
+};
+
+use super::csv_convert::read_headers;
+use crate::{get_writer, CsvReaderOpts};
+
+// longer rows are cut in diagnostics
+const MAX_ROW_TEXT: usize = 200;
+
+/// What to do with records the CSV parser rejects.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum BadRowPolicy {
+    #[default]
+    Fail,
+    Skip,
+    // skip them and copy them to this CSV file, after the header
+    SkipTo(String),
+}
+
+/// A malformed record, with where it starts in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadRow {
+    pub line: u64,
+    pub byte: u64,
+    // field counts, for records whose length differs from the first one
+    pub expected: Option<u64>,
+    pub found: Option<u64>,
+    pub message: String,
+    pub text: String,
+}
+
+/// Reads records like `Reader::read_record`, reporting or skipping malformed ones.
+pub struct RecordReader<R> {
+    reader: Reader<R>,
+    skip: bool,
+    rejects: Option<(String, Writer<Box<dyn Write + Send>>)>,
+    delimiter: u8,
+    trim: bool,
+    raw: ByteRecord,
+    skipped: u64,
+    first: Option<BadRow>,
+}
+
+impl<R: Read> RecordReader<R> {
+    pub fn new(mut reader: Reader<R>, opts: &CsvReaderOpts, policy: &BadRowPolicy) -> Result<Self> {
+        let rejects = match policy {
+            BadRowPolicy::SkipTo(path) => {
+                let mut writer = WriterBuilder::new()
+                    .delimiter(opts.delimiter)
+                    .flexible(true)
+                    .from_writer(get_writer(path)?);
+                if let Some(headers) = read_headers(&mut reader, opts)? {
+                    writer.write_record(&headers)?;
+                }
+                Some((path.clone(), writer))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            reader,
+            skip: *policy != BadRowPolicy::Fail,
+            rejects,
+            delimiter: opts.delimiter,
+            trim: opts.trim,
+            raw: ByteRecord::new(),
+            skipped: 0,
+            first: None,
+        })
+    }
+
+    pub fn read_record(&mut self, record: &mut StringRecord) -> Result<bool> {
+        loop {
+            let bad = match self.reader.read_byte_record(&mut self.raw) {
+                Ok(false) => return Ok(false),
+                Ok(true) => match StringRecord::from_byte_record(std::mem::take(&mut self.raw)) {
+                    Ok(r) => {
+                        *record = r;
+                        // the csv crate skips trimming the first headerless byte record
+                        if self.trim {
+                            record.trim();
+                        }
+                        return Ok(true);
+                    }
+                    Err(e) => {
+                        let field = e.utf8_error().field() + 1;
+                        self.raw = e.into_byte_record();
+                        self.bad_row(format!("invalid UTF-8 in field {}", field), None)
+                    }
+                },
+                Err(e) => match e.kind() {
+                    ErrorKind::UnequalLengths {
+                        pos,
+                        expected_len,
+                        len,
+                    } => {
+                        if let Some(pos) = pos {
+                            self.raw.set_position(Some(pos.clone()));
+                        }
+                        let message = format!("expected {} fields, found {}", expected_len, len);
+                        self.bad_row(message, Some((*expected_len, *len)))
+                    }
+                    _ => return Err(e.into()),
+                },
+            };
+            if !self.skip {
+                return Err(bad.into());
+            }
+            if let Some((_, writer)) = &mut self.rejects {
+                writer.write_byte_record(&self.raw)?;
+            }
+            self.skipped += 1;
+            self.first.get_or_insert(bad);
+        }
+    }
+
+    /// Go on with the records of `reader`, keeping the rejects file and the skipped rows, for
+    /// inputs read one after another.
+    pub fn set_reader(&mut self, reader: Reader<R>) {
+        self.reader = reader;
+    }
+
+    pub fn position(&self) -> &Position {
+        self.reader.position()
+    }
+
+    /// Flush the rejects file and report skipped rows on stderr.
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some((_, writer)) = &mut self.rejects {
+            writer.flush()?;
+        }
+        if let Some(first) = &self.first {
+            let target = match &self.rejects {
+                Some((path, _)) => format!(" (written to {})", path),
+                None => String::new(),
+            };
+            eprintln!(
+                "warning: skipped {} bad rows{}, the first one at {}",
+                self.skipped, target, first
+            );
+        }
+        Ok(())
+    }
+
+    fn bad_row(&self, message: String, counts: Option<(u64, u64)>) -> BadRow {
+        let (line, byte) = self.raw.position().map_or((0, 0), |p| (p.line(), p.byte()));
+        let fields: Vec<_> = self.raw.iter().map(String::from_utf8_lossy).collect();
+        let mut text = fields.join(&(self.delimiter as char).to_string());
+        if text.chars().count() > MAX_ROW_TEXT {
+            text = text.chars().take(MAX_ROW_TEXT).chain(Some('…')).collect();
+        }
+        BadRow {
+            line,
+            byte,
+            expected: counts.map(|(expected, _)| expected),
+            found: counts.map(|(_, found)| found),
+            message,
+            text,
+        }
+    }
+}
+
+impl fmt::Display for BadRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, byte {}: {}\n    {}",
+            self.line, self.byte, self.message, self.text
+        )
+    }
+}
+
+impl std::error::Error for BadRow {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_reader() -> Result<()> {
+        let data: &[u8] = b"a,b\n1,2\n3\n4,5\n6,\xff\n";
+        let opts = CsvReaderOpts::default();
+        let mut records = RecordReader::new(opts.from_reader(data), &opts, &BadRowPolicy::Fail)?;
+        let mut record = StringRecord::new();
+        assert!(records.read_record(&mut record)?);
+        let err = records.read_record(&mut record).unwrap_err();
+        let bad = err.downcast_ref::<BadRow>().unwrap();
+        assert_eq!((bad.line, bad.byte), (3, 8));
+        assert_eq!((bad.expected, bad.found), (Some(2), Some(1)));
+        assert_eq!(
+            bad.to_string(),
+            "line 3, byte 8: expected 2 fields, found 1\n    3"
+        );
+
+        let mut records = RecordReader::new(opts.from_reader(data), &opts, &BadRowPolicy::Skip)?;
+        let mut kept = Vec::new();
+        while records.read_record(&mut record)? {
+            kept.push(record.clone());
+        }
+        assert_eq!(
+            kept,
+            vec![
+                StringRecord::from(vec!["1", "2"]),
+                StringRecord::from(vec!["4", "5"])
+            ]
+        );
+        assert_eq!(records.skipped, 2);
+        Ok(())
+    }
+}
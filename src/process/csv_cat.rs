@@ -0,0 +1,209 @@
+use anyhow::{bail, Context, Result};
+use csv::{StringRecord, WriterBuilder};
+use std::fs;
+
+use crate::{get_writer, BadRowPolicy, CsvReaderOpts, RecordReader};
+
+/// Concatenate CSV files into `output`, matching columns by header name.
+///
+/// Every file must have the columns of the first one unless `union` is set, in which case new
+/// columns are appended and cells missing from a file are left empty. The headers are checked
+/// before `output` is created.
+pub fn process_csv_cat(
+    inputs: &[String],
+    output: &str,
+    opts: &CsvReaderOpts,
+    union: bool,
+    policy: &BadRowPolicy,
+) -> Result<()> {
+    if inputs.iter().filter(|input| *input == "-").count() > 1 {
+        bail!("Only one of the concatenated files can be read from stdin");
+    }
+    let rejects = match policy {
+        BadRowPolicy::SkipTo(path) => Some(path.as_str()),
+        _ => None,
+    };
+    for target in Some(output).into_iter().chain(rejects) {
+        if let Some(input) = inputs.iter().find(|input| same_file(input, target)) {
+            bail!(
+                "{} is both an input and an output, write to another file",
+                input
+            );
+        }
+    }
+    let mut readers = inputs
+        .iter()
+        .map(|input| opts.open(input))
+        .collect::<Result<Vec<_>>>()?;
+
+    // where each output column sits in each file, None keeps records as they are
+    let mut columns = Vec::new();
+    let mut layouts = Vec::with_capacity(readers.len());
+    if opts.has_headers {
+        let mut headers = Vec::with_capacity(readers.len());
+        for reader in &mut readers {
+            headers.push(reader.headers()?.clone());
+        }
+        columns = headers
+            .first()
+            .map_or_else(Vec::new, |h| h.iter().map(String::from).collect());
+        for (input, file_headers) in inputs.iter().zip(&headers).skip(1) {
+            let missing: Vec<&str> = columns
+                .iter()
+                .map(String::as_str)
+                .filter(|c| !file_headers.iter().any(|h| h == *c))
+                .collect();
+            let extra: Vec<&str> = file_headers
+                .iter()
+                .filter(|h| !columns.iter().any(|c| c == h))
+                .collect();
+            if !union && (!missing.is_empty() || !extra.is_empty()) {
+                bail!(
+                    "{} does not have the columns of {} (missing: [{}], extra: [{}]), use --union to combine them",
+                    input,
+                    inputs[0],
+                    missing.join(", "),
+                    extra.join(", ")
+                );
+            }
+            columns.extend(extra.into_iter().map(String::from));
+        }
+        for file_headers in &headers {
+            let positions: Vec<Option<usize>> = columns
+                .iter()
+                .map(|c| file_headers.iter().position(|h| h == c))
+                .collect();
+            layouts.push(Some(positions));
+        }
+    } else {
+        // without names there is nothing to align
+        layouts.resize(readers.len(), None);
+    }
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(opts.delimiter)
+        .from_writer(get_writer(output)?);
+    if opts.has_headers {
+        writer.write_record(&columns)?;
+    }
+    // one record reader goes through the files, so bad rows of all of them share the rejects
+    let mut records: Option<RecordReader<_>> = None;
+    let mut record = StringRecord::new();
+    for ((input, reader), positions) in inputs.iter().zip(readers).zip(&layouts) {
+        let records = match &mut records {
+            Some(records) => {
+                records.set_reader(reader);
+                records
+            }
+            None => records.insert(RecordReader::new(reader, opts, policy)?),
+        };
+        while records
+            .read_record(&mut record)
+            .with_context(|| format!("Failed to read {}", input))?
+        {
+            match positions {
+                Some(positions) => writer.write_record(
+                    positions
+                        .iter()
+                        .map(|p| p.and_then(|i| record.get(i)).unwrap_or_default()),
+                )?,
+                None => writer.write_record(&record)?,
+            }
+        }
+    }
+    writer.flush()?;
+    if let Some(records) = &mut records {
+        records.finish()?;
+    }
+    Ok(())
+}
+
+// whether two paths name the same existing file
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BadRow;
+
+    #[test]
+    fn test_cat() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        fs::write(path("a.csv"), "id,name\n1,Anna\n")?;
+        fs::write(path("b.csv"), "name,id\nBob,2\n")?;
+        fs::write(path("c.csv"), "id,team\n3,x\n")?;
+        let opts = CsvReaderOpts::default();
+        let policy = BadRowPolicy::Fail;
+
+        process_csv_cat(
+            &[path("a.csv"), path("b.csv")],
+            &path("out.csv"),
+            &opts,
+            false,
+            &policy,
+        )?;
+        assert_eq!(
+            fs::read_to_string(path("out.csv"))?,
+            "id,name\n1,Anna\n2,Bob\n"
+        );
+
+        let inputs = [path("a.csv"), path("c.csv")];
+        assert!(process_csv_cat(&inputs, &path("out.csv"), &opts, false, &policy).is_err());
+        process_csv_cat(&inputs, &path("out.csv"), &opts, true, &policy)?;
+        assert_eq!(
+            fs::read_to_string(path("out.csv"))?,
+            "id,name,team\n1,Anna,\n3,,x\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_leaves_no_output_on_error() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        fs::write(path("a.csv"), "id,name\n1,Anna\n")?;
+        fs::write(path("c.csv"), "id,team\n3,x\n")?;
+        let opts = CsvReaderOpts::default();
+        let policy = BadRowPolicy::Fail;
+
+        let inputs = [path("a.csv"), path("c.csv")];
+        assert!(process_csv_cat(&inputs, &path("out.csv"), &opts, false, &policy).is_err());
+        assert!(!dir.path().join("out.csv").exists());
+
+        // writing over an input would truncate it before it is read
+        let err = process_csv_cat(&inputs, &path("a.csv"), &opts, true, &policy).unwrap_err();
+        assert!(err.to_string().contains("both an input and an output"));
+        assert_eq!(fs::read_to_string(path("a.csv"))?, "id,name\n1,Anna\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_bad_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        fs::write(path("a.csv"), "id,name\n1,Anna\n2\n")?;
+        fs::write(path("b.csv"), "name,id\nBob,3\nx,y,z\n")?;
+        let opts = CsvReaderOpts::default();
+        let inputs = [path("a.csv"), path("b.csv")];
+
+        let err = process_csv_cat(&inputs, &path("out.csv"), &opts, false, &BadRowPolicy::Fail)
+            .unwrap_err();
+        assert!(err.to_string().contains("a.csv"));
+        assert!(err.downcast_ref::<BadRow>().is_some());
+
+        let policy = BadRowPolicy::SkipTo(path("bad.csv"));
+        process_csv_cat(&inputs, &path("out.csv"), &opts, false, &policy)?;
+        assert_eq!(
+            fs::read_to_string(path("out.csv"))?,
+            "id,name\n1,Anna\n3,Bob\n"
+        );
+        assert_eq!(fs::read_to_string(path("bad.csv"))?, "id,name\n2\nx,y,z\n");
+        Ok(())
+    }
+}
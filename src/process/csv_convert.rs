@@ -1,24 +1,424 @@
 use anyhow::Result;
-use csv::Reader;
-use serde_json::Value;
-use std::fs;
+use csv::{Reader, ReaderBuilder, StringRecord, Trim};
+use serde_json::{Map, Value};
+use std::{
+    fs,
+    io::{Read, Write},
+};
 
-use crate::cli::OutputFormat;
+use crate::{
+    cli::{InferMode, InputEncoding, OutputFormat, SqlDialect, TomlNullPolicy},
+    decode_reader, get_reader, get_writer, infer_values, rows_writer, sheet_reader, stream_csv,
+    Aggregate, BadRowPolicy, ColumnSelection, CsvSchema, Filter, MaskRule, RecordReader, RowReader,
+    RowWriter, SortKey,
+};
 
-pub fn process_csv(input: &str, output: String, format: OutputFormat) -> Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut ret = Vec::with_capacity(128);
-    let headers = reader.headers()?.clone();
-    for result in reader.records() {
-        let record = result?;
-        let json_value = headers.iter().zip(record.iter()).collect::<Value>();
-        ret.push(json_value)
+use super::csv_stream::pipeline_writer;
+
+// inputs larger than this are converted record by record instead of in memory
+const STREAM_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct CsvReaderOpts {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub escape: Option<u8>,
+    pub comment: Option<u8>,
+    pub flexible: bool,
+    pub trim: bool,
+    pub has_headers: bool,
+    pub encoding: InputEncoding,
+    // sheet of .xlsx/.ods input, by name or 1-based index
+    pub sheet: Option<String>,
+}
+
+impl Default for CsvReaderOpts {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            comment: None,
+            flexible: false,
+            trim: false,
+            has_headers: true,
+            encoding: InputEncoding::default(),
+            sheet: None,
+        }
+    }
+}
+
+impl CsvReaderOpts {
+    pub fn builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            // with a custom escape char, doubled quotes are no longer an escape
+            .double_quote(self.escape.is_none())
+            .comment(self.comment)
+            .flexible(self.flexible)
+            .trim(if self.trim { Trim::All } else { Trim::None })
+            .has_headers(self.has_headers);
+        builder
     }
+
+    pub fn from_reader<R: Read>(&self, reader: R) -> Reader<R> {
+        self.builder().from_reader(reader)
+    }
+
+    /// Open `input` (`-` for stdin), transcoding it to UTF-8 first.
+    pub fn open(&self, input: &str) -> Result<Reader<Box<dyn Read>>> {
+        Ok(self.from_reader(self.open_raw(input)?))
+    }
+
+    /// The transcoded bytes of `input`, before CSV parsing; spreadsheets are converted to CSV.
+    pub fn open_raw(&self, input: &str) -> Result<Box<dyn Read>> {
+        let reader = sheet_reader(
+            input,
+            get_reader(input)?,
+            self.sheet.as_deref(),
+            self.delimiter,
+        )?;
+        decode_reader(reader, self.encoding)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvConvertOpts {
+    pub infer: InferMode,
+    // per-column type overrides, skip inference for these columns
+    pub schema: CsvSchema,
+    // TOML needs a root table, rows are emitted as an array of tables under this key
+    pub toml_root: String,
+    pub toml_null: TomlNullPolicy,
+    pub stream: bool,
+    // applied last, so schema and inference still refer to the original header names
+    pub columns: ColumnSelection,
+    pub filter: Option<Filter>,
+    pub dedupe: Vec<String>,
+    pub group_by: Vec<String>,
+    // count only when empty
+    pub aggregates: Vec<Aggregate>,
+    pub sort: Vec<SortKey>,
+    // applied before projection, so rules use the original column names
+    pub masks: Vec<MaskRule>,
+    pub mask_key: Option<Vec<u8>>,
+    // build nested objects from `address.city` and arrays from `tags[0]` style headers
+    pub nest: bool,
+    pub bad_rows: BadRowPolicy,
+    // SQL output: table name, dialect and rows per INSERT
+    pub sql_table: String,
+    pub sql_dialect: SqlDialect,
+    pub sql_batch_size: usize,
+    // html output without the surrounding page
+    pub html_fragment: bool,
+    // parser threads, 1 parses on the calling thread and 0 uses every core
+    pub threads: usize,
+}
+
+impl Default for CsvConvertOpts {
+    fn default() -> Self {
+        Self {
+            infer: InferMode::default(),
+            schema: CsvSchema::default(),
+            toml_root: "rows".to_string(),
+            toml_null: TomlNullPolicy::default(),
+            stream: false,
+            columns: ColumnSelection::default(),
+            filter: None,
+            dedupe: Vec::new(),
+            group_by: Vec::new(),
+            aggregates: Vec::new(),
+            sort: Vec::new(),
+            masks: Vec::new(),
+            mask_key: None,
+            nest: false,
+            bad_rows: BadRowPolicy::default(),
+            sql_table: "data".to_string(),
+            sql_dialect: SqlDialect::default(),
+            sql_batch_size: 500,
+            html_fragment: false,
+            threads: 1,
+        }
+    }
+}
+
+pub fn process_csv(
+    input: &str,
+    output: String,
+    format: OutputFormat,
+    opts: &CsvReaderOpts,
+    convert_opts: &CsvConvertOpts,
+) -> Result<()> {
+    if should_stream(input, convert_opts)? {
+        return stream_csv(input, &output, format, opts, convert_opts);
+    }
+    let mut ret = Vec::new();
+    convert_rows(input, || Ok(Box::new(&mut ret)), opts, convert_opts)?;
     let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
-        OutputFormat::Toml => toml::to_string(&ret)?,
+        OutputFormat::Json => serde_json::to_vec_pretty(&ret)?,
+        OutputFormat::Ndjson => to_ndjson(&ret)?.into_bytes(),
+        OutputFormat::Yaml => serde_yaml::to_string(&ret)?.into_bytes(),
+        OutputFormat::Toml => to_toml(ret, convert_opts)?.into_bytes(),
+        OutputFormat::Msgpack => rmp_serde::to_vec_named(&ret)?,
+        // formats that are only written row by row; CBOR too, so it is the same indefinite-length
+        // array as when streaming
+        OutputFormat::Cbor
+        | OutputFormat::Parquet
+        | OutputFormat::Sql
+        | OutputFormat::Sqlite
+        | OutputFormat::Markdown
+        | OutputFormat::Html => {
+            let mut buf = Vec::new();
+            let mut writer = rows_writer(format, &mut buf, convert_opts, &ret);
+            for row in &ret {
+                writer.write_row(row)?;
+            }
+            writer.finish()?;
+            drop(writer);
+            buf
+        }
     };
-    fs::write(output, content)?;
-    Ok(())
+    let mut writer = get_writer(&output)?;
+    writer.write_all(&content)?;
+    Ok(writer.flush()?)
+}
+
+// stdin has no size, so it is only streamed on request
+pub(crate) fn should_stream(input: &str, convert_opts: &CsvConvertOpts) -> Result<bool> {
+    let large = input != "-" && fs::metadata(input)?.len() > STREAM_THRESHOLD;
+    Ok(convert_opts.stream || large)
+}
+
+// in memory counterpart of `stream_rows`, column inference sees every row at once
+pub(crate) fn convert_rows<'a>(
+    input: &str,
+    make_writer: impl FnOnce() -> Result<Box<dyn RowWriter + 'a>>,
+    opts: &CsvReaderOpts,
+    convert_opts: &CsvConvertOpts,
+) -> Result<()> {
+    let mut reader = RowReader::open(input, opts, convert_opts.threads, &convert_opts.bad_rows)?;
+    if let Some(filter) = &convert_opts.filter {
+        filter.check_columns(reader.headers())?;
+    }
+    let mut writer = pipeline_writer(make_writer()?, reader.headers(), convert_opts)?;
+    let mut rows = Vec::with_capacity(128);
+    while let Some(row) = reader.next_row(&|_| Ok(()))? {
+        rows.push(row);
+    }
+    reader.finish()?;
+    infer_values(&mut rows, convert_opts)?;
+    for row in &rows {
+        if writer.done() {
+            break;
+        }
+        if convert_opts.filter.as_ref().is_none_or(|f| f.eval(row)) {
+            writer.write_row(row)?;
+        }
+    }
+    writer.finish()
+}
+
+pub fn read_records<R: Read>(mut reader: Reader<R>, opts: &CsvReaderOpts) -> Result<Vec<Value>> {
+    let headers = read_headers(&mut reader, opts)?;
+    let mut records = RecordReader::new(reader, opts, &BadRowPolicy::Fail)?;
+    let mut ret = Vec::with_capacity(128);
+    let mut record = StringRecord::new();
+    while records.read_record(&mut record)? {
+        ret.push(record_to_value(headers.as_ref(), &record));
+    }
+    Ok(ret)
+}
+
+pub(crate) fn read_headers<R: Read>(
+    reader: &mut Reader<R>,
+    opts: &CsvReaderOpts,
+) -> Result<Option<StringRecord>> {
+    if opts.has_headers {
+        Ok(Some(reader.headers()?.clone()))
+    } else {
+        Ok(None)
+    }
+}
+
+fn to_ndjson(rows: &[Value]) -> Result<String> {
+    let mut ret = String::new();
+    for row in rows {
+        ret.push_str(&serde_json::to_string(row)?);
+        ret.push('\n');
+    }
+    Ok(ret)
+}
+
+fn to_toml(rows: Vec<Value>, opts: &CsvConvertOpts) -> Result<String> {
+    let rows = rows
+        .into_iter()
+        .map(|row| replace_nulls(row, opts.toml_null))
+        .collect();
+    let mut root = Map::new();
+    root.insert(opts.toml_root.clone(), Value::Array(rows));
+    Ok(toml::to_string(&root)?)
+}
+
+// TOML has no null, so nulls are either dropped or written as empty strings
+pub(crate) fn replace_nulls(value: Value, policy: TomlNullPolicy) -> Value {
+    match value {
+        Value::Object(map) => map
+            .into_iter()
+            .filter_map(|(k, v)| match (v, policy) {
+                (Value::Null, TomlNullPolicy::Omit) => None,
+                (v, policy) => Some((k, replace_nulls(v, policy))),
+            })
+            .collect::<Map<_, _>>()
+            .into(),
+        Value::Array(values) => values
+            .into_iter()
+            .filter(|v| !(v.is_null() && policy == TomlNullPolicy::Omit))
+            .map(|v| replace_nulls(v, policy))
+            .collect(),
+        Value::Null => Value::from(""),
+        v => v,
+    }
+}
+
+pub(crate) fn record_to_value(headers: Option<&StringRecord>, record: &StringRecord) -> Value {
+    match headers {
+        Some(headers) => headers.iter().zip(record.iter()).collect(),
+        // headerless input gets generated keys: col_1, col_2, ...
+        None => record
+            .iter()
+            .enumerate()
+            .map(|(i, field)| (format!("col_{}", i + 1), Value::from(field)))
+            .collect::<Map<_, _>>()
+            .into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_records_with_delimiter() -> Result<()> {
+        let data = "Name;City\nAnna;Berlin\n";
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            ..Default::default()
+        };
+        let ret = read_records(opts.from_reader(data.as_bytes()), &opts)?;
+        assert_eq!(ret, vec![json!({"Name": "Anna", "City": "Berlin"})]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_records_keep_header_order() -> Result<()> {
+        let data = "name,age,city\nAnna,30,Berlin\n";
+        let opts = CsvReaderOpts::default();
+        let ret = read_records(opts.from_reader(data.as_bytes()), &opts)?;
+        assert_eq!(
+            serde_json::to_string(&ret[0])?,
+            r#"{"name":"Anna","age":"30","city":"Berlin"}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_records_without_header() -> Result<()> {
+        let data = "a\t b\n# skipped\nc\td\te\n";
+        let opts = CsvReaderOpts {
+            delimiter: b'\t',
+            comment: Some(b'#'),
+            flexible: true,
+            trim: true,
+            has_headers: false,
+            ..Default::default()
+        };
+        let ret = read_records(opts.from_reader(data.as_bytes()), &opts)?;
+        assert_eq!(
+            ret,
+            vec![
+                json!({"col_1": "a", "col_2": "b"}),
+                json!({"col_1": "c", "col_2": "d", "col_3": "e"}),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_records_with_escape() -> Result<()> {
+        let data = "k,v\n1,\"say \\\"hi\\\"\"\n";
+        let opts = CsvReaderOpts {
+            escape: Some(b'\\'),
+            ..Default::default()
+        };
+        let ret = read_records(opts.from_reader(data.as_bytes()), &opts)?;
+        assert_eq!(ret, vec![json!({"k": "1", "v": "say \"hi\""})]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_toml() -> Result<()> {
+        let rows = vec![json!({"a": 1, "b": null}), json!({"a": 2, "b": "x"})];
+        let mut opts = CsvConvertOpts::default();
+        assert_eq!(
+            to_toml(rows.clone(), &opts)?,
+            "[[rows]]\na = 1\n\n[[rows]]\na = 2\nb = \"x\"\n"
+        );
+
+        opts.toml_root = "players".to_string();
+        opts.toml_null = TomlNullPolicy::Empty;
+        assert_eq!(
+            to_toml(rows, &opts)?,
+            "[[players]]\na = 1\nb = \"\"\n\n[[players]]\na = 2\nb = \"x\"\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_matches_in_memory() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        fs::write(
+            &input,
+            "name,age,score\nAnna,30,1\nBob,,2.5\n\"C, D\",41,\n",
+        )?;
+        let input = input.to_str().unwrap();
+        let formats = [
+            OutputFormat::Json,
+            OutputFormat::Ndjson,
+            OutputFormat::Yaml,
+            OutputFormat::Toml,
+            OutputFormat::Msgpack,
+            OutputFormat::Cbor,
+            OutputFormat::Parquet,
+            OutputFormat::Sql,
+            OutputFormat::Sqlite,
+            OutputFormat::Markdown,
+            OutputFormat::Html,
+        ];
+        for format in formats {
+            let mut outputs = Vec::new();
+            for stream in [false, true] {
+                let output = dir.path().join(format!("out-{}-{}", format, stream));
+                let convert_opts = CsvConvertOpts {
+                    stream,
+                    ..Default::default()
+                };
+                let path = output.to_str().unwrap().to_string();
+                process_csv(
+                    input,
+                    path,
+                    format,
+                    &CsvReaderOpts::default(),
+                    &convert_opts,
+                )?;
+                outputs.push(fs::read(&output)?);
+            }
+            assert_eq!(outputs[0], outputs[1], "{} output differs", format);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,335 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, fmt::Write};
+
+use super::{
+    csv_convert::{read_headers, read_records},
+    csv_select::resolve_column,
+};
+use crate::CsvReaderOpts;
+
+/// Rows added, removed and changed between two versions of a CSV file, matched by key.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct CsvDiff {
+    pub key: Vec<String>,
+    pub added_columns: Vec<String>,
+    pub removed_columns: Vec<String>,
+    // removed rows in the order of the old file, the others in the order of the new one
+    pub added: Vec<Value>,
+    pub removed: Vec<Value>,
+    pub changed: Vec<RowChange>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RowChange {
+    pub key: Vec<String>,
+    pub cells: Vec<CellChange>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CellChange {
+    pub column: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+struct Version {
+    columns: Vec<String>,
+    rows: Vec<Value>,
+}
+
+/// Compare `old` and `new`, whose rows are identified by the `key` columns. Cells of the columns
+/// in both files are compared as text, so "1" and "1.0" differ.
+pub fn process_csv_diff(
+    old: &str,
+    new: &str,
+    key: &[String],
+    opts: &CsvReaderOpts,
+) -> Result<CsvDiff> {
+    if old == "-" && new == "-" {
+        bail!("Only one of the compared files can be read from stdin");
+    }
+    let old_version = read_version(old, opts)?;
+    let new_version = read_version(new, opts)?;
+    diff_versions(old_version, new_version, key, old, new, opts)
+}
+
+fn read_version(input: &str, opts: &CsvReaderOpts) -> Result<Version> {
+    let mut reader = opts.open(input)?;
+    let headers = read_headers(&mut reader, opts)?;
+    let rows = read_records(reader, opts)?;
+    let mut columns: Vec<String> = match headers {
+        Some(headers) => headers.iter().map(String::from).collect(),
+        None => Vec::new(),
+    };
+    // headerless rows may differ in length, take every generated key
+    for column in rows
+        .iter()
+        .filter_map(Value::as_object)
+        .flat_map(|r| r.keys())
+    {
+        if !columns.contains(column) {
+            columns.push(column.clone());
+        }
+    }
+    Ok(Version { columns, rows })
+}
+
+fn diff_versions(
+    old: Version,
+    new: Version,
+    key: &[String],
+    old_name: &str,
+    new_name: &str,
+    opts: &CsvReaderOpts,
+) -> Result<CsvDiff> {
+    if key.is_empty() {
+        bail!("Expected at least one key column");
+    }
+    let key = resolve_key(key, &old, old_name, opts)?;
+    let new_key = resolve_key(&key, &new, new_name, opts)?;
+    let old_index = index_rows(&old.rows, &key, old_name)?;
+    let new_index = index_rows(&new.rows, &new_key, new_name)?;
+
+    let mut diff = CsvDiff {
+        added_columns: new
+            .columns
+            .iter()
+            .filter(|c| !old.columns.contains(c))
+            .cloned()
+            .collect(),
+        removed_columns: old
+            .columns
+            .iter()
+            .filter(|c| !new.columns.contains(c))
+            .cloned()
+            .collect(),
+        key: key.clone(),
+        ..Default::default()
+    };
+    for (row, row_key) in old.rows.iter().zip(old_index.keys_in_order) {
+        if !new_index.rows.contains_key(&row_key) {
+            diff.removed.push(row.clone());
+        }
+    }
+    // added and removed columns are reported once, not for every row
+    let columns: Vec<&String> = old
+        .columns
+        .iter()
+        .filter(|c| new.columns.contains(c))
+        .collect();
+    for (row, row_key) in new.rows.iter().zip(new_index.keys_in_order) {
+        let Some(&i) = old_index.rows.get(&row_key) else {
+            diff.added.push(row.clone());
+            continue;
+        };
+        let cells: Vec<CellChange> = columns
+            .iter()
+            .filter_map(|column| {
+                let old = old.rows[i].get(column).cloned().unwrap_or_default();
+                let new = row.get(column).cloned().unwrap_or_default();
+                (old != new).then(|| CellChange {
+                    column: (*column).clone(),
+                    old,
+                    new,
+                })
+            })
+            .collect();
+        if !cells.is_empty() {
+            diff.changed.push(RowChange {
+                key: row_key,
+                cells,
+            });
+        }
+    }
+    Ok(diff)
+}
+
+fn resolve_key(
+    key: &[String],
+    version: &Version,
+    name: &str,
+    opts: &CsvReaderOpts,
+) -> Result<Vec<String>> {
+    key.iter()
+        .map(|column| {
+            let headers = opts
+                .has_headers
+                .then(|| csv::StringRecord::from(version.columns.clone()));
+            let column = resolve_column(column, headers.as_ref())?;
+            if !version.columns.contains(&column) {
+                bail!("Key column {} not found in {}", column, name);
+            }
+            Ok(column)
+        })
+        .collect()
+}
+
+struct RowIndex {
+    keys_in_order: Vec<Vec<String>>,
+    rows: HashMap<Vec<String>, usize>,
+}
+
+fn index_rows(rows: &[Value], key: &[String], name: &str) -> Result<RowIndex> {
+    let mut index = RowIndex {
+        keys_in_order: Vec::with_capacity(rows.len()),
+        rows: HashMap::with_capacity(rows.len()),
+    };
+    for (i, row) in rows.iter().enumerate() {
+        let row_key = row_key(row, key);
+        if index.rows.insert(row_key.clone(), i).is_some() {
+            bail!("Duplicate key {} in {}", key_text(key, &row_key), name);
+        }
+        index.keys_in_order.push(row_key);
+    }
+    Ok(index)
+}
+
+/// Render `diff` for review, one line per added or removed row and per changed cell.
+pub fn render_diff_report(diff: &CsvDiff, old: &str, new: &str) -> String {
+    let mut ret = format!("--- {}\n+++ {}\n", old, new);
+    if !diff.removed_columns.is_empty() {
+        writeln!(ret, "removed columns: {}", diff.removed_columns.join(", ")).unwrap();
+    }
+    if !diff.added_columns.is_empty() {
+        writeln!(ret, "added columns: {}", diff.added_columns.join(", ")).unwrap();
+    }
+    for row in &diff.removed {
+        writeln!(ret, "- {}", key_text(&diff.key, &row_key(row, &diff.key))).unwrap();
+    }
+    for row in &diff.added {
+        writeln!(ret, "+ {}", key_text(&diff.key, &row_key(row, &diff.key))).unwrap();
+    }
+    for change in &diff.changed {
+        writeln!(ret, "~ {}", key_text(&diff.key, &change.key)).unwrap();
+        for cell in &change.cells {
+            writeln!(ret, "    {}: {} -> {}", cell.column, cell.old, cell.new).unwrap();
+        }
+    }
+    writeln!(
+        ret,
+        "{} added, {} removed, {} changed",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len()
+    )
+    .unwrap();
+    ret
+}
+
+/// An RFC 6902 JSON Patch turning the old rows into the new ones, for a document that nests
+/// rows under their key values (`{"<id>": {"name": ...}}`). Replaced and removed values are
+/// preceded by a `test` of the old value; columns only in one file are not patched.
+pub fn diff_to_json_patch(diff: &CsvDiff) -> Value {
+    let row_path = |key: &[String]| -> String {
+        key.iter()
+            .map(|k| format!("/{}", escape_pointer(k)))
+            .collect()
+    };
+    let mut ops = Vec::new();
+    for row in &diff.removed {
+        let path = row_path(&row_key(row, &diff.key));
+        ops.push(json!({"op": "test", "path": path, "value": row}));
+        ops.push(json!({"op": "remove", "path": path}));
+    }
+    for change in &diff.changed {
+        for cell in &change.cells {
+            let path = format!("{}/{}", row_path(&change.key), escape_pointer(&cell.column));
+            match (&cell.old, &cell.new) {
+                (Value::Null, new) => ops.push(json!({"op": "add", "path": path, "value": new})),
+                (old, Value::Null) => {
+                    ops.push(json!({"op": "test", "path": path, "value": old}));
+                    ops.push(json!({"op": "remove", "path": path}));
+                }
+                (old, new) => {
+                    ops.push(json!({"op": "test", "path": path, "value": old}));
+                    ops.push(json!({"op": "replace", "path": path, "value": new}));
+                }
+            }
+        }
+    }
+    for row in &diff.added {
+        let path = row_path(&row_key(row, &diff.key));
+        ops.push(json!({"op": "add", "path": path, "value": row}));
+    }
+    Value::Array(ops)
+}
+
+fn row_key(row: &Value, key: &[String]) -> Vec<String> {
+    key.iter()
+        .map(|k| row.get(k).and_then(Value::as_str).unwrap_or("").to_string())
+        .collect()
+}
+
+fn key_text(key: &[String], values: &[String]) -> String {
+    key.iter()
+        .zip(values)
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// JSON Pointer escapes `~` and `/` in reference tokens
+fn escape_pointer(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(data: &str) -> Result<Version> {
+        let opts = CsvReaderOpts::default();
+        let mut reader = opts.from_reader(data.as_bytes());
+        let headers = read_headers(&mut reader, &opts)?.unwrap();
+        Ok(Version {
+            columns: headers.iter().map(String::from).collect(),
+            rows: read_records(reader, &opts)?,
+        })
+    }
+
+    #[test]
+    fn test_diff_versions() -> Result<()> {
+        let old = version("id,name,team\n1,Anna,a\n2,Bob,b\n3,Cem,a\n")?;
+        let new = version("id,name,city\n3,Cem,Turin\n1,Ann,\n4,Dan,Milan\n")?;
+        let opts = CsvReaderOpts::default();
+        let diff = diff_versions(old, new, &["id".to_string()], "old", "new", &opts)?;
+        assert_eq!(diff.added_columns, vec!["city"]);
+        assert_eq!(diff.removed_columns, vec!["team"]);
+        assert_eq!(
+            diff.removed,
+            vec![json!({"id": "2", "name": "Bob", "team": "b"})]
+        );
+        assert_eq!(
+            render_diff_report(&diff, "old", "new"),
+            "--- old\n+++ new\nremoved columns: team\nadded columns: city\n- id=2\n+ id=4\n\
+             ~ id=1\n    name: \"Anna\" -> \"Ann\"\n1 added, 1 removed, 1 changed\n"
+        );
+        let patch = diff_to_json_patch(&diff);
+        assert_eq!(patch[1], json!({"op": "remove", "path": "/2"}));
+        assert_eq!(
+            patch[2],
+            json!({"op": "test", "path": "/1/name", "value": "Anna"})
+        );
+        assert_eq!(
+            patch[3],
+            json!({"op": "replace", "path": "/1/name", "value": "Ann"})
+        );
+        assert_eq!(patch[4]["path"], "/4");
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_duplicate_key() -> Result<()> {
+        let old = version("id,name\n1,a\n1,b\n")?;
+        let new = version("id,name\n1,a\n")?;
+        let opts = CsvReaderOpts::default();
+        let err = diff_versions(old, new, &["id".to_string()], "old.csv", "new.csv", &opts);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Duplicate key id=1 in old.csv"
+        );
+        Ok(())
+    }
+}
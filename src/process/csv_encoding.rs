@@ -0,0 +1,136 @@
+use anyhow::Result;
+use encoding_rs::{
+    DecoderResult, Encoding, GBK, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252,
+};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use std::io::{Cursor, Read};
+
+use crate::cli::InputEncoding;
+
+// bytes looked at to guess the encoding of input without a BOM
+const SNIFF_LEN: u64 = 64 * 1024;
+
+/// Transcode `reader` to UTF-8, dropping any BOM.
+pub fn decode_reader(mut reader: Box<dyn Read>, encoding: InputEncoding) -> Result<Box<dyn Read>> {
+    let encoding = match encoding {
+        InputEncoding::Label(encoding) => Some(encoding),
+        InputEncoding::Auto => {
+            let mut sample = Vec::new();
+            reader.by_ref().take(SNIFF_LEN).read_to_end(&mut sample)?;
+            // with a BOM the decoder picks the encoding itself
+            let detected = match Encoding::for_bom(&sample) {
+                Some(_) => None,
+                None => Some(detect_encoding(&sample)),
+            };
+            reader = Box::new(Cursor::new(sample).chain(reader));
+            // valid UTF-8 is passed through as is
+            detected.filter(|e| *e != UTF_8)
+        }
+    };
+    let reader = DecodeReaderBytesBuilder::new()
+        .encoding(encoding)
+        .bom_override(true)
+        .strip_bom(true)
+        .utf8_passthru(true)
+        .build(reader);
+    Ok(Box::new(reader))
+}
+
+/// Guess the encoding of `sample`, which has no BOM.
+pub fn detect_encoding(sample: &[u8]) -> &'static Encoding {
+    // ASCII text in UTF-16 is every other byte NUL, which is also valid UTF-8
+    if let Some(encoding) = detect_utf16(sample) {
+        return encoding;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => return UTF_8,
+        // the sample may end in the middle of a character
+        Err(e) if e.error_len().is_none() => return UTF_8,
+        Err(_) => {}
+    }
+    // Japanese Shift-JIS also decodes as GBK, so it is tried first; Chinese GBK decoded as
+    // Shift-JIS comes out mostly as half-width katakana, which real Japanese text rarely is
+    if let Some(text) = decode_strict(SHIFT_JIS, sample) {
+        let non_ascii = text.chars().filter(|c| !c.is_ascii()).count();
+        let katakana = text
+            .chars()
+            .filter(|c| ('\u{ff61}'..='\u{ff9f}').contains(c))
+            .count();
+        if katakana * 2 < non_ascii {
+            return SHIFT_JIS;
+        }
+    }
+    if decode_strict(GBK, sample).is_some() {
+        return GBK;
+    }
+    WINDOWS_1252
+}
+
+fn detect_utf16(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs == 0 {
+        return None;
+    }
+    let nuls = |offset: usize| {
+        sample
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|b| **b == 0)
+            .count()
+    };
+    let (even, odd) = (nuls(0), nuls(1));
+    if odd * 3 > pairs && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 3 > pairs && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+fn decode_strict(encoding: &'static Encoding, sample: &[u8]) -> Option<String> {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let len = decoder.max_utf8_buffer_length_without_replacement(sample.len())?;
+    let mut ret = String::with_capacity(len);
+    let (result, _) = decoder.decode_to_string_without_replacement(sample, &mut ret, false);
+    matches!(result, DecoderResult::InputEmpty).then_some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Result<String> {
+        let mut ret = String::new();
+        decode_reader(Box::new(Cursor::new(bytes.to_vec())), InputEncoding::Auto)?
+            .read_to_string(&mut ret)?;
+        Ok(ret)
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        let text = "名前,都市\n山田太郎,東京\n";
+        let (sjis, _, _) = SHIFT_JIS.encode(text);
+        assert_eq!(detect_encoding(&sjis), SHIFT_JIS);
+        let (gbk, _, _) = GBK.encode("姓名,城市\n张伟,北京\n");
+        assert_eq!(detect_encoding(&gbk), GBK);
+        assert_eq!(detect_encoding("a,b\n".as_bytes()), UTF_8);
+        assert_eq!(detect_encoding(b"caf\xe9\n"), WINDOWS_1252);
+    }
+
+    #[test]
+    fn test_decode_reader() -> Result<()> {
+        assert_eq!(decode(b"\xef\xbb\xbfname\n")?, "name\n");
+        let utf16: Vec<u8> = "name,é\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(decode(&utf16)?, "name,é\n");
+        let with_bom = [&[0xff, 0xfe][..], &utf16].concat();
+        assert_eq!(decode(&with_bom)?, "name,é\n");
+        let (gbk, _, _) = GBK.encode("姓名,城市\n张伟,北京\n");
+        assert_eq!(decode(&gbk)?, "姓名,城市\n张伟,北京\n");
+        Ok(())
+    }
+}
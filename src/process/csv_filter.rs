@@ -0,0 +1,357 @@
+use anyhow::{anyhow, bail, Result};
+use csv::StringRecord;
+use regex::Regex;
+use serde_json::Value;
+use std::{cmp::Ordering, iter::Peekable, str::FromStr, vec::IntoIter};
+
+/// A parsed `--where` expression, e.g. `Age > 30 and Position == "Defender"`.
+///
+/// Columns are bare identifiers or `backtick quoted` when they contain spaces; literals are
+/// numbers, "strings", true, false and null.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Compare(Operand, CmpOp, Operand),
+    Match(Operand, Regex, bool),
+    IsNull(Operand, bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Column(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+impl Filter {
+    pub fn eval(&self, row: &Value) -> bool {
+        match self {
+            Filter::And(a, b) => a.eval(row) && b.eval(row),
+            Filter::Or(a, b) => a.eval(row) || b.eval(row),
+            Filter::Not(f) => !f.eval(row),
+            Filter::Compare(a, op, b) => compare(a.value(row), *op, b.value(row)),
+            Filter::Match(a, re, negate) => {
+                let matched = match a.value(row) {
+                    Value::Null => false,
+                    Value::String(s) => re.is_match(s),
+                    v => re.is_match(&v.to_string()),
+                };
+                matched != *negate
+            }
+            Filter::IsNull(a, negate) => a.value(row).is_null() != *negate,
+        }
+    }
+
+    /// Make sure every column the expression refers to exists in `headers`.
+    pub fn check_columns(&self, headers: Option<&StringRecord>) -> Result<()> {
+        let Some(headers) = headers else {
+            return Ok(());
+        };
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        match columns.iter().find(|c| !headers.iter().any(|h| h == **c)) {
+            Some(c) => bail!("Unknown column in --where: {}", c),
+            None => Ok(()),
+        }
+    }
+
+    fn collect_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        let mut push = |o: &'a Operand| {
+            if let Operand::Column(c) = o {
+                columns.push(c);
+            }
+        };
+        match self {
+            Filter::And(a, b) | Filter::Or(a, b) => {
+                a.collect_columns(columns);
+                b.collect_columns(columns);
+            }
+            Filter::Not(f) => f.collect_columns(columns),
+            Filter::Compare(a, _, b) => {
+                push(a);
+                push(b);
+            }
+            Filter::Match(a, _, _) | Filter::IsNull(a, _) => push(a),
+        }
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, row: &'a Value) -> &'a Value {
+        match self {
+            Operand::Column(c) => row.get(c).unwrap_or(&Value::Null),
+            Operand::Literal(v) => v,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(s)?.into_iter().peekable();
+        let filter = parse_or(&mut tokens)?;
+        match tokens.next() {
+            None => Ok(filter),
+            Some(t) => bail!("Unexpected {:?} in filter expression", t),
+        }
+    }
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn parse_or(tokens: &mut Tokens) -> Result<Filter> {
+    let mut left = parse_and(tokens)?;
+    while eat_keyword(tokens, "or") {
+        left = Filter::Or(Box::new(left), Box::new(parse_and(tokens)?));
+    }
+    Ok(left)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Filter> {
+    let mut left = parse_not(tokens)?;
+    while eat_keyword(tokens, "and") {
+        left = Filter::And(Box::new(left), Box::new(parse_not(tokens)?));
+    }
+    Ok(left)
+}
+
+fn parse_not(tokens: &mut Tokens) -> Result<Filter> {
+    if eat_keyword(tokens, "not") {
+        return Ok(Filter::Not(Box::new(parse_not(tokens)?)));
+    }
+    if tokens.peek() == Some(&Token::LParen) {
+        tokens.next();
+        let filter = parse_or(tokens)?;
+        if tokens.next() != Some(Token::RParen) {
+            bail!("Missing closing parenthesis in filter expression");
+        }
+        return Ok(filter);
+    }
+    parse_comparison(tokens)
+}
+
+fn parse_comparison(tokens: &mut Tokens) -> Result<Filter> {
+    let left = parse_operand(tokens)?;
+    if eat_keyword(tokens, "is") {
+        let negate = eat_keyword(tokens, "not");
+        if !eat_keyword(tokens, "null") {
+            bail!("Expected null after is");
+        }
+        return Ok(Filter::IsNull(left, negate));
+    }
+    let op = match tokens.next() {
+        Some(Token::Op(op)) => op,
+        Some(Token::Ident(kw)) if kw.eq_ignore_ascii_case("matches") => "=~",
+        t => bail!("Expected a comparison operator, found {:?}", t),
+    };
+    if op == "=~" || op == "!~" {
+        let Some(Token::Str(pattern)) = tokens.next() else {
+            bail!("Expected a quoted regex after {}", op);
+        };
+        return Ok(Filter::Match(left, Regex::new(&pattern)?, op == "!~"));
+    }
+    let op = match op {
+        "==" => CmpOp::Eq,
+        "!=" => CmpOp::Ne,
+        "<" => CmpOp::Lt,
+        "<=" => CmpOp::Le,
+        ">" => CmpOp::Gt,
+        _ => CmpOp::Ge,
+    };
+    Ok(Filter::Compare(left, op, parse_operand(tokens)?))
+}
+
+fn parse_operand(tokens: &mut Tokens) -> Result<Operand> {
+    let operand = match tokens.next() {
+        Some(Token::Str(s)) => Operand::Literal(Value::String(s)),
+        Some(Token::Num(n)) => Operand::Literal(Value::from(n)),
+        Some(Token::Ident(id)) => match id.as_str() {
+            "true" => Operand::Literal(Value::Bool(true)),
+            "false" => Operand::Literal(Value::Bool(false)),
+            "null" => Operand::Literal(Value::Null),
+            _ => Operand::Column(id),
+        },
+        t => bail!("Expected a column or a value, found {:?}", t),
+    };
+    Ok(operand)
+}
+
+fn eat_keyword(tokens: &mut Tokens, keyword: &str) -> bool {
+    match tokens.peek() {
+        Some(Token::Ident(id)) if id.eq_ignore_ascii_case(keyword) => {
+            tokens.next();
+            true
+        }
+        _ => false,
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    const OPS: [&str; 8] = ["==", "!=", "<=", ">=", "=~", "!~", "<", ">"];
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' {
+                    Token::LParen
+                } else {
+                    Token::RParen
+                });
+            }
+            '"' | '\'' | '`' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => value.push(escaped),
+                            None => bail!("Unterminated string in filter expression"),
+                        },
+                        Some((_, q)) if q == c => break,
+                        Some((_, ch)) => value.push(ch),
+                        None => bail!("Unterminated string in filter expression"),
+                    }
+                }
+                // backticks quote column names, everything else is a string literal
+                tokens.push(if c == '`' {
+                    Token::Ident(value)
+                } else {
+                    Token::Str(value)
+                });
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let end = s[i..]
+                    .find(|ch: char| !(ch.is_ascii_alphanumeric() || matches!(ch, '.' | '-' | '+')))
+                    .map_or(s.len(), |n| i + n);
+                let n = s[i..end]
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid number in filter expression: {}", &s[i..end]))?;
+                tokens.push(Token::Num(n));
+                while chars.peek().is_some_and(|&(j, _)| j < end) {
+                    chars.next();
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let end = s[i..]
+                    .find(|ch: char| !(ch.is_alphanumeric() || matches!(ch, '_' | '.')))
+                    .map_or(s.len(), |n| i + n);
+                tokens.push(Token::Ident(s[i..end].to_string()));
+                while chars.peek().is_some_and(|&(j, _)| j < end) {
+                    chars.next();
+                }
+            }
+            _ => {
+                let Some(op) = OPS.iter().find(|op| s[i..].starts_with(**op)) else {
+                    bail!("Unexpected character {:?} in filter expression", c);
+                };
+                tokens.push(Token::Op(op));
+                for _ in 0..op.len() {
+                    chars.next();
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn compare(a: &Value, op: CmpOp, b: &Value) -> bool {
+    let ordering = match (a, b) {
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        // uninferred columns hold strings, so numbers are compared numerically when possible
+        (a, b) => match (as_number(a), as_number(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => None,
+        },
+    };
+    match (op, ordering) {
+        (CmpOp::Ne, None) => true,
+        (_, None) => false,
+        (CmpOp::Eq, Some(o)) => o == Ordering::Equal,
+        (CmpOp::Ne, Some(o)) => o != Ordering::Equal,
+        (CmpOp::Lt, Some(o)) => o == Ordering::Less,
+        (CmpOp::Le, Some(o)) => o != Ordering::Greater,
+        (CmpOp::Gt, Some(o)) => o == Ordering::Greater,
+        (CmpOp::Ge, Some(o)) => o != Ordering::Less,
+    }
+}
+
+pub(crate) fn as_number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filter_eval() -> Result<()> {
+        let filter: Filter = r#"Age > 30 and Position == "Defender""#.parse()?;
+        assert!(filter.eval(&json!({"Age": 34, "Position": "Defender"})));
+        assert!(!filter.eval(&json!({"Age": 34, "Position": "Goalkeeper"})));
+        // string values from uninferred columns still compare as numbers
+        assert!(filter.eval(&json!({"Age": "31", "Position": "Defender"})));
+        assert!(!filter.eval(&json!({"Age": null, "Position": "Defender"})));
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_regex_null_and_precedence() -> Result<()> {
+        let filter: Filter = "`Kit Number` is null or Name =~ '^G' and not (Age < 40)".parse()?;
+        assert!(filter.eval(&json!({"Name": "Buffon"})));
+        assert!(filter.eval(&json!({"Kit Number": 77, "Name": "Gigi", "Age": 41})));
+        assert!(!filter.eval(&json!({"Kit Number": 1, "Name": "Gigi", "Age": 29})));
+        assert!(!filter.eval(&json!({"Kit Number": 1, "Name": "Dybala", "Age": 41})));
+
+        let filter: Filter = "x is not null and x != -1.5".parse()?;
+        assert!(filter.eval(&json!({"x": 2})));
+        assert!(!filter.eval(&json!({"x": -1.5})));
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_errors() {
+        assert!("Age >".parse::<Filter>().is_err());
+        assert!("(Age > 1".parse::<Filter>().is_err());
+        assert!("Name =~ '['".parse::<Filter>().is_err());
+        assert!("Name == 'x".parse::<Filter>().is_err());
+
+        let filter: Filter = "Agee > 1".parse().unwrap();
+        let headers = StringRecord::from(vec!["Age"]);
+        assert!(filter.check_columns(Some(&headers)).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use csv::WriterBuilder;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+};
+
+use crate::cli::InputFormat;
+
+/// Convert an array of objects in `format` back into CSV, one column per flattened key.
+pub fn process_csv_from(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: InputFormat,
+    delimiter: u8,
+    toml_root: &str,
+) -> Result<()> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    let rows = parse_rows(&buf, format, toml_root)?;
+    let rows: Vec<Map<String, Value>> = rows.into_iter().map(flatten_row).collect();
+
+    // union of all keys, in the order they are first seen
+    let mut seen = HashSet::new();
+    let mut headers = Vec::new();
+    for key in rows.iter().flat_map(|row| row.keys()) {
+        if seen.insert(key.as_str()) {
+            headers.push(key.as_str());
+        }
+    }
+
+    let mut wtr = WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+    wtr.write_record(&headers)?;
+    for row in &rows {
+        wtr.write_record(
+            headers
+                .iter()
+                .map(|h| row.get(*h).map_or_else(String::new, cell)),
+        )?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn parse_rows(buf: &[u8], format: InputFormat, toml_root: &str) -> Result<Vec<Value>> {
+    let rows = match format {
+        InputFormat::Json => into_rows(serde_json::from_slice(buf)?),
+        InputFormat::Ndjson => {
+            let mut rows = Vec::new();
+            for line in std::str::from_utf8(buf)?.lines() {
+                if !line.trim().is_empty() {
+                    rows.push(serde_json::from_str(line)?);
+                }
+            }
+            rows
+        }
+        InputFormat::Yaml => {
+            // accepts a single sequence as well as one document per row
+            let mut rows = Vec::new();
+            for doc in serde_yaml::Deserializer::from_slice(buf) {
+                rows.extend(into_rows(Value::deserialize(doc)?));
+            }
+            rows
+        }
+        InputFormat::Toml => {
+            let table: toml::Table = toml::from_str(std::str::from_utf8(buf)?)?;
+            let value = toml_to_json(toml::Value::Table(table));
+            match value {
+                Value::Object(mut map) => match map.remove(toml_root) {
+                    Some(rows) => into_rows(rows),
+                    None => vec![Value::Object(map)],
+                },
+                v => into_rows(v),
+            }
+        }
+    };
+    Ok(rows)
+}
+
+fn into_rows(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(rows) => rows,
+        Value::Null => vec![],
+        v => vec![v],
+    }
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(values) => values.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => table
+            .into_iter()
+            .map(|(k, v)| (k, toml_to_json(v)))
+            .collect::<Map<_, _>>()
+            .into(),
+    }
+}
+
+// nested objects become dotted columns (`address.city`), arrays indexed ones (`tags[0]`)
+fn flatten_row(row: Value) -> Map<String, Value> {
+    let mut ret = Map::new();
+    match row {
+        Value::Object(map) => {
+            for (k, v) in map {
+                flatten_into(&mut ret, k, v);
+            }
+        }
+        v => {
+            ret.insert("value".to_string(), v);
+        }
+    }
+    ret
+}
+
+fn flatten_into(ret: &mut Map<String, Value>, prefix: String, value: Value) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (k, v) in map {
+                flatten_into(ret, format!("{}.{}", prefix, k), v);
+            }
+        }
+        Value::Array(values) if !values.is_empty() => {
+            for (i, v) in values.into_iter().enumerate() {
+                flatten_into(ret, format!("{}[{}]", prefix, i), v);
+            }
+        }
+        v => {
+            ret.insert(prefix, v);
+        }
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(input: &str, format: InputFormat) -> Result<String> {
+        let mut out = Vec::new();
+        process_csv_from(&mut input.as_bytes(), &mut out, format, b',', "rows")?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[test]
+    fn test_process_csv_from_json() -> Result<()> {
+        let input = r#"[
+            {"name": "Anna", "address": {"city": "Berlin, DE"}, "tags": ["a", "b"]},
+            {"name": "Bob", "age": 30, "address": {"city": null}}
+        ]"#;
+        assert_eq!(
+            convert(input, InputFormat::Json)?,
+            "name,address.city,tags[0],tags[1],age\n\
+             Anna,\"Berlin, DE\",a,b,\n\
+             Bob,,,,30\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_from_yaml_and_toml() -> Result<()> {
+        let yaml = "---\na: 1\n---\na: 2\nb: true\n";
+        assert_eq!(convert(yaml, InputFormat::Yaml)?, "a,b\n1,\n2,true\n");
+        let toml = "[[rows]]\na = 1\n\n[[rows]]\na = 2\nb = \"x\"\n";
+        assert_eq!(convert(toml, InputFormat::Toml)?, "a,b\n1,\n2,x\n");
+        let ndjson = "{\"a\":1}\n\n{\"b\":2}\n";
+        assert_eq!(convert(ndjson, InputFormat::Ndjson)?, "a,b\n1,\n,2\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+use anyhow::{bail, Result};
+use csv::StringRecord;
+use serde_json::{Map, Value};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use super::{csv_filter::as_number, csv_select::resolve_column, csv_sort::compare_values};
+use crate::RowWriter;
+
+/// A `--agg` aggregation computed per group: `count` or `sum|avg|min|max:column`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Aggregate {
+    Count,
+    Sum(String),
+    Avg(String),
+    Min(String),
+    Max(String),
+}
+
+/// Keeps the first row for every distinct combination of the `--dedupe-by` columns.
+#[derive(Debug)]
+pub struct Dedupe {
+    columns: Vec<String>,
+    seen: HashSet<String>,
+}
+
+/// Groups rows by the `--group-by` columns and aggregates each group into one row.
+#[derive(Debug)]
+pub struct GroupBy {
+    columns: Vec<String>,
+    aggregates: Vec<Aggregate>,
+    // groups in the order they are first seen, looked up by their serialized key
+    groups: Vec<(Vec<Value>, Vec<AggState>)>,
+    index: HashMap<String, usize>,
+}
+
+pub struct DedupeWriter<'a> {
+    inner: Box<dyn RowWriter + 'a>,
+    dedupe: Dedupe,
+}
+
+pub struct GroupWriter<'a> {
+    inner: Box<dyn RowWriter + 'a>,
+    group: GroupBy,
+}
+
+#[derive(Debug, Default)]
+struct AggState {
+    count: usize,
+    sum: f64,
+    // sums stay exact integers as long as every value is one
+    int_sum: i128,
+    inexact: bool,
+    best: Option<Value>,
+}
+
+impl Aggregate {
+    /// Name of the output column, e.g. `sum_Age`.
+    pub fn name(&self) -> String {
+        match self {
+            Aggregate::Count => "count".to_string(),
+            Aggregate::Sum(c) => format!("sum_{}", c),
+            Aggregate::Avg(c) => format!("avg_{}", c),
+            Aggregate::Min(c) => format!("min_{}", c),
+            Aggregate::Max(c) => format!("max_{}", c),
+        }
+    }
+
+    fn resolve(&self, headers: Option<&StringRecord>) -> Result<Self> {
+        Ok(match self {
+            Aggregate::Count => Aggregate::Count,
+            Aggregate::Sum(c) => Aggregate::Sum(resolve_column(c, headers)?),
+            Aggregate::Avg(c) => Aggregate::Avg(resolve_column(c, headers)?),
+            Aggregate::Min(c) => Aggregate::Min(resolve_column(c, headers)?),
+            Aggregate::Max(c) => Aggregate::Max(resolve_column(c, headers)?),
+        })
+    }
+
+    fn column(&self) -> Option<&str> {
+        match self {
+            Aggregate::Count => None,
+            Aggregate::Sum(c) | Aggregate::Avg(c) | Aggregate::Min(c) | Aggregate::Max(c) => {
+                Some(c)
+            }
+        }
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let agg = match s.split_once(':') {
+            None if s == "count" => Aggregate::Count,
+            Some(("sum", c)) if !c.is_empty() => Aggregate::Sum(c.to_string()),
+            Some(("avg", c)) if !c.is_empty() => Aggregate::Avg(c.to_string()),
+            Some(("min", c)) if !c.is_empty() => Aggregate::Min(c.to_string()),
+            Some(("max", c)) if !c.is_empty() => Aggregate::Max(c.to_string()),
+            _ => bail!(
+                "Unsupported aggregation: {}, expected count or sum|avg|min|max:column",
+                s
+            ),
+        };
+        Ok(agg)
+    }
+}
+
+impl Dedupe {
+    pub fn new(columns: &[String], headers: Option<&StringRecord>) -> Result<Self> {
+        Ok(Self {
+            columns: resolve_columns(columns, headers)?,
+            seen: HashSet::new(),
+        })
+    }
+
+    /// Whether `row` is the first one with its key.
+    pub fn insert(&mut self, row: &Value) -> bool {
+        let (_, key) = row_key(row, &self.columns);
+        self.seen.insert(key)
+    }
+}
+
+impl GroupBy {
+    pub fn new(
+        columns: &[String],
+        aggregates: &[Aggregate],
+        headers: Option<&StringRecord>,
+    ) -> Result<Self> {
+        let aggregates = if aggregates.is_empty() {
+            vec![Aggregate::Count]
+        } else {
+            aggregates
+                .iter()
+                .map(|a| a.resolve(headers))
+                .collect::<Result<_>>()?
+        };
+        Ok(Self {
+            columns: resolve_columns(columns, headers)?,
+            aggregates,
+            groups: Vec::new(),
+            index: HashMap::new(),
+        })
+    }
+
+    /// Output column names: the group columns followed by one column per aggregation.
+    pub fn headers(&self) -> StringRecord {
+        let names = self.aggregates.iter().map(Aggregate::name);
+        self.columns.iter().cloned().chain(names).collect()
+    }
+
+    pub fn add(&mut self, row: &Value) {
+        let (values, key) = row_key(row, &self.columns);
+        let i = *self.index.entry(key).or_insert_with(|| {
+            let states = self.aggregates.iter().map(|_| AggState::default());
+            self.groups.push((values, states.collect()));
+            self.groups.len() - 1
+        });
+        for (agg, state) in self.aggregates.iter().zip(&mut self.groups[i].1) {
+            let value = agg.column().and_then(|c| row.get(c));
+            state.add(agg, value);
+        }
+    }
+
+    /// Take the aggregated rows, one per group in first-seen order.
+    pub fn drain(&mut self) -> Vec<Value> {
+        self.index.clear();
+        self.groups
+            .drain(..)
+            .map(|(values, states)| {
+                let mut row: Map<_, _> = self.columns.iter().cloned().zip(values).collect();
+                for (agg, state) in self.aggregates.iter().zip(states) {
+                    row.insert(agg.name(), state.finish(agg));
+                }
+                Value::Object(row)
+            })
+            .collect()
+    }
+}
+
+impl AggState {
+    fn add(&mut self, agg: &Aggregate, value: Option<&Value>) {
+        let value = match (agg, value) {
+            (Aggregate::Count, _) => {
+                self.count += 1;
+                return;
+            }
+            (_, None | Some(Value::Null)) => return,
+            (_, Some(v)) => v,
+        };
+        match agg {
+            Aggregate::Sum(_) | Aggregate::Avg(_) => {
+                if let Some(n) = as_number(value) {
+                    self.count += 1;
+                    self.sum += n;
+                    match as_integer(value).and_then(|i| self.int_sum.checked_add(i)) {
+                        Some(sum) => self.int_sum = sum,
+                        None => self.inexact = true,
+                    }
+                }
+            }
+            Aggregate::Min(_) | Aggregate::Max(_) => {
+                let want = if matches!(agg, Aggregate::Min(_)) {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+                if self
+                    .best
+                    .as_ref()
+                    .is_none_or(|best| compare_values(value, best) == want)
+                {
+                    self.best = Some(value.clone());
+                }
+            }
+            Aggregate::Count => unreachable!("handled above"),
+        }
+    }
+
+    fn finish(self, agg: &Aggregate) -> Value {
+        match agg {
+            Aggregate::Count => Value::from(self.count),
+            Aggregate::Sum(_) if self.count == 0 => Value::Null,
+            Aggregate::Sum(_) if self.inexact => Value::from(self.sum),
+            // JSON numbers past i64 are u64 or floats
+            Aggregate::Sum(_) => i64::try_from(self.int_sum)
+                .map(Value::from)
+                .or_else(|_| u64::try_from(self.int_sum).map(Value::from))
+                .unwrap_or_else(|_| Value::from(self.int_sum as f64)),
+            Aggregate::Avg(_) if self.count == 0 => Value::Null,
+            Aggregate::Avg(_) if self.inexact => Value::from(self.sum / self.count as f64),
+            Aggregate::Avg(_) => Value::from(self.int_sum as f64 / self.count as f64),
+            Aggregate::Min(_) | Aggregate::Max(_) => self.best.unwrap_or(Value::Null),
+        }
+    }
+}
+
+impl<'a> DedupeWriter<'a> {
+    pub fn new(inner: Box<dyn RowWriter + 'a>, dedupe: Dedupe) -> Self {
+        Self { inner, dedupe }
+    }
+}
+
+impl RowWriter for DedupeWriter<'_> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        if self.dedupe.insert(row) {
+            self.inner.write_row(row)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+
+    fn done(&self) -> bool {
+        self.inner.done()
+    }
+}
+
+impl<'a> GroupWriter<'a> {
+    pub fn new(inner: Box<dyn RowWriter + 'a>, group: GroupBy) -> Self {
+        Self { inner, group }
+    }
+}
+
+impl RowWriter for GroupWriter<'_> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        self.group.add(row);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for row in self.group.drain() {
+            self.inner.write_row(&row)?;
+        }
+        self.inner.finish()
+    }
+}
+
+fn as_integer(value: &Value) -> Option<i128> {
+    match value {
+        Value::Number(n) => n.as_i64().map(i128::from).or(n.as_u64().map(i128::from)),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn resolve_columns(columns: &[String], headers: Option<&StringRecord>) -> Result<Vec<String>> {
+    columns.iter().map(|c| resolve_column(c, headers)).collect()
+}
+
+// the values of `columns` in `row`, and a string key identifying them
+fn row_key(row: &Value, columns: &[String]) -> (Vec<Value>, String) {
+    let values: Vec<Value> = columns
+        .iter()
+        .map(|c| row.get(c).cloned().unwrap_or(Value::Null))
+        .collect();
+    let key = Value::Array(values.clone()).to_string();
+    (values, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_group_by_aggregates() -> Result<()> {
+        let headers = StringRecord::from(vec!["team", "age", "score"]);
+        let aggs: Vec<Aggregate> = ["count", "sum:age", "avg:3", "min:age", "max:score"]
+            .iter()
+            .map(|s| s.parse())
+            .collect::<Result<_>>()?;
+        let mut group = GroupBy::new(&["team".to_string()], &aggs, Some(&headers))?;
+        assert_eq!(
+            group.headers(),
+            StringRecord::from(vec![
+                "team",
+                "count",
+                "sum_age",
+                "avg_score",
+                "min_age",
+                "max_score"
+            ])
+        );
+        for row in [
+            json!({"team": "a", "age": 30, "score": 1.5}),
+            json!({"team": "b", "age": null, "score": "x"}),
+            json!({"team": "a", "age": 20, "score": 2.5}),
+        ] {
+            group.add(&row);
+        }
+        assert_eq!(
+            group.drain(),
+            vec![
+                json!({"team": "a", "count": 2, "sum_age": 50, "avg_score": 2.0, "min_age": 20, "max_score": 2.5}),
+                json!({"team": "b", "count": 1, "sum_age": null, "avg_score": null, "min_age": null, "max_score": "x"}),
+            ]
+        );
+        assert!("sum".parse::<Aggregate>().is_err());
+
+        // integer sums stay exact past 2^53 and i64, a fraction turns them into floats
+        let aggs = vec!["sum:n".parse()?];
+        let mut group = GroupBy::new(&["g".to_string()], &aggs, None)?;
+        for row in [
+            json!({"g": "a", "n": 9007199254740993_i64}),
+            json!({"g": "a", "n": "1"}),
+            json!({"g": "b", "n": i64::MAX}),
+            json!({"g": "b", "n": 1}),
+            json!({"g": "c", "n": 1}),
+            json!({"g": "c", "n": 0.5}),
+        ] {
+            group.add(&row);
+        }
+        assert_eq!(
+            group.drain(),
+            vec![
+                json!({"g": "a", "sum_n": 9007199254740994_i64}),
+                json!({"g": "b", "sum_n": 9223372036854775808_u64}),
+                json!({"g": "c", "sum_n": 1.5}),
+            ]
+        );
+        assert!(GroupBy::new(&["nope".to_string()], &[], Some(&headers)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedupe() -> Result<()> {
+        let headers = StringRecord::from(vec!["a", "b"]);
+        let mut dedupe = Dedupe::new(&["a".to_string()], Some(&headers))?;
+        assert!(dedupe.insert(&json!({"a": 1, "b": 1})));
+        assert!(!dedupe.insert(&json!({"a": 1, "b": 2})));
+        assert!(dedupe.insert(&json!({"a": "1", "b": 2})));
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fmt};
+
+use crate::{cli::InferMode, get_content, CsvConvertOpts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Null,
+    #[serde(alias = "bool")]
+    Boolean,
+    #[serde(alias = "int")]
+    Integer,
+    Float,
+    Date,
+    Datetime,
+    #[serde(alias = "str")]
+    String,
+}
+
+pub type CsvSchema = HashMap<String, ColumnType>;
+
+impl ColumnType {
+    pub fn detect(s: &str) -> Self {
+        if is_null(s) {
+            ColumnType::Null
+        } else if s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("false") {
+            ColumnType::Boolean
+        } else if is_integer(s) {
+            ColumnType::Integer
+        } else if is_float(s) {
+            ColumnType::Float
+        } else if NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok() {
+            ColumnType::Date
+        } else if is_datetime(s) {
+            ColumnType::Datetime
+        } else {
+            ColumnType::String
+        }
+    }
+
+    // the narrowest type that can hold values of both types
+    pub fn merge(self, other: Self) -> Self {
+        use ColumnType::*;
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Null, t) | (t, Null) => t,
+            (Integer, Float) | (Float, Integer) => Float,
+            _ => String,
+        }
+    }
+
+    pub fn convert(self, s: &str) -> Result<Value> {
+        if is_null(s) && self != ColumnType::String {
+            return Ok(Value::Null);
+        }
+        let value = match self {
+            ColumnType::Null => Value::Null,
+            ColumnType::Boolean => Value::Bool(s.to_ascii_lowercase().parse()?),
+            ColumnType::Integer => Value::from(s.parse::<i64>()?),
+            // integers are floats too once a column mixes both
+            ColumnType::Float if is_float(s) || is_integer(s) => Value::from(s.parse::<f64>()?),
+            ColumnType::Float => return Err(anyhow!("invalid float literal")),
+            ColumnType::Date => {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")?;
+                Value::from(s)
+            }
+            ColumnType::Datetime if is_datetime(s) => Value::from(s),
+            ColumnType::Datetime => return Err(anyhow!("invalid datetime literal")),
+            ColumnType::String => Value::from(s),
+        };
+        Ok(value)
+    }
+}
+
+impl From<ColumnType> for &'static str {
+    fn from(ty: ColumnType) -> Self {
+        match ty {
+            ColumnType::Null => "null",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::Datetime => "datetime",
+            ColumnType::String => "string",
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+pub fn load_schema(path: &str) -> Result<CsvSchema> {
+    // JSON is valid YAML, so one parser covers both file types
+    let content = get_content(path)?;
+    Ok(serde_yaml::from_slice(&content)?)
+}
+
+/// Convert the string fields of every row in place according to `opts`.
+pub fn infer_values(rows: &mut [Value], opts: &CsvConvertOpts) -> Result<()> {
+    let mut types = opts.schema.clone();
+    if opts.infer == InferMode::Column {
+        for row in rows.iter() {
+            detect_row_types(row, &mut types, &opts.schema);
+        }
+    }
+    for row in rows.iter_mut() {
+        infer_row(row, &types, opts.infer)?;
+    }
+    Ok(())
+}
+
+/// Widen the column types in `types` with the values of `row`, leaving `schema` columns alone.
+pub fn detect_row_types(row: &Value, types: &mut CsvSchema, schema: &CsvSchema) {
+    for (key, value) in fields(row) {
+        if schema.contains_key(key) {
+            continue;
+        }
+        let ty = ColumnType::detect(value);
+        types
+            .entry(key.to_owned())
+            .and_modify(|t| *t = t.merge(ty))
+            .or_insert(ty);
+    }
+}
+
+/// Convert a single row, using `types` where known and `mode` for everything else.
+pub fn infer_row(row: &mut Value, types: &CsvSchema, mode: InferMode) -> Result<()> {
+    let Value::Object(map) = row else {
+        return Ok(());
+    };
+    for (key, value) in map.iter_mut() {
+        let Value::String(s) = value else {
+            continue;
+        };
+        let ty = match (types.get(key), mode) {
+            (Some(ty), _) => *ty,
+            (None, InferMode::None) => continue,
+            (None, _) => ColumnType::detect(s),
+        };
+        *value = ty
+            .convert(s)
+            .map_err(|e| anyhow!("column {:?}: cannot convert {:?} to {}: {}", key, s, ty, e))?;
+    }
+    Ok(())
+}
+
+fn fields(row: &Value) -> impl Iterator<Item = (&String, &str)> {
+    row.as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(k, v)| v.as_str().map(|s| (k, s)))
+}
+
+fn is_null(s: &str) -> bool {
+    s.is_empty() || s.eq_ignore_ascii_case("null")
+}
+
+// zero-padded codes such as zip codes or ids should stay strings
+fn is_zero_padded(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit()
+}
+
+fn is_integer(s: &str) -> bool {
+    !is_zero_padded(s) && s.parse::<i64>().is_ok()
+}
+
+fn is_float(s: &str) -> bool {
+    // reject "inf", "NaN" and friends which f64 happily parses; integers too large for i64 need
+    // a `.` or exponent, or they would lose digits as floats
+    !is_zero_padded(s)
+        && s.bytes().any(|b| b.is_ascii_digit())
+        && s.bytes().any(|b| matches!(b, b'.' | b'e' | b'E'))
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'-' | b'+' | b'e' | b'E'))
+        && s.parse::<f64>().is_ok()
+}
+
+fn is_datetime(s: &str) -> bool {
+    DateTime::parse_from_rfc3339(s).is_ok()
+        || NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").is_ok()
+        || NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_column_type() {
+        assert_eq!(ColumnType::detect(""), ColumnType::Null);
+        assert_eq!(ColumnType::detect("TRUE"), ColumnType::Boolean);
+        assert_eq!(ColumnType::detect("-42"), ColumnType::Integer);
+        assert_eq!(ColumnType::detect("007"), ColumnType::String);
+        assert_eq!(ColumnType::detect("1.5e3"), ColumnType::Float);
+        assert_eq!(ColumnType::detect("NaN"), ColumnType::String);
+        assert_eq!(
+            ColumnType::detect("12345678901234567890"),
+            ColumnType::String
+        );
+        assert_eq!(
+            ColumnType::detect("12345678901234567890.0"),
+            ColumnType::Float
+        );
+        assert_eq!(ColumnType::detect("2024-02-29"), ColumnType::Date);
+        assert_eq!(ColumnType::detect("2023-02-29"), ColumnType::String);
+        assert_eq!(
+            ColumnType::detect("2024-05-01T10:00:00Z"),
+            ColumnType::Datetime
+        );
+    }
+
+    #[test]
+    fn test_infer_values_by_column() -> Result<()> {
+        let mut rows = vec![
+            json!({"a": "1", "b": "x", "c": "true"}),
+            json!({"a": "2.5", "b": "", "c": ""}),
+        ];
+        let opts = CsvConvertOpts {
+            infer: InferMode::Column,
+            ..Default::default()
+        };
+        infer_values(&mut rows, &opts)?;
+        assert_eq!(
+            rows,
+            vec![
+                json!({"a": 1.0, "b": "x", "c": true}),
+                json!({"a": 2.5, "b": "", "c": null}),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_infer_values_by_cell_with_schema() -> Result<()> {
+        let mut rows = vec![json!({"id": "007", "n": "3"}), json!({"id": "8", "n": "x"})];
+        let opts = CsvConvertOpts {
+            infer: InferMode::Cell,
+            schema: HashMap::from([("id".to_string(), ColumnType::String)]),
+            ..Default::default()
+        };
+        infer_values(&mut rows, &opts)?;
+        assert_eq!(
+            rows,
+            vec![json!({"id": "007", "n": 3}), json!({"id": "8", "n": "x"})]
+        );
+
+        let opts = CsvConvertOpts {
+            infer: InferMode::None,
+            schema: HashMap::from([("n".to_string(), ColumnType::Integer)]),
+            ..Default::default()
+        };
+        let mut rows = vec![json!({"n": "x"})];
+        assert!(infer_values(&mut rows, &opts).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::{bail, Result};
+use csv::{Reader, StringRecord};
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufWriter, Read},
+};
+
+use super::{
+    csv_convert::{read_headers, read_records},
+    csv_select::resolve_column,
+};
+use crate::{
+    cli::{JoinKind, OutputFormat},
+    get_writer, infer_values, rows_writer, CsvConvertOpts, CsvReaderOpts,
+};
+
+/// How to join two CSV files; key columns are header names or 1-based indexes.
+#[derive(Debug, Clone)]
+pub struct JoinSpec {
+    pub kind: JoinKind,
+    pub left_on: Vec<String>,
+    pub right_on: Vec<String>,
+    // appended to non-key columns present in both files
+    pub left_suffix: String,
+    pub right_suffix: String,
+}
+
+struct Table {
+    headers: Option<StringRecord>,
+    columns: Vec<String>,
+    rows: Vec<Value>,
+}
+
+/// Join the CSV files `left` and `right` and write the result in `format`.
+pub fn process_csv_join(
+    left: &str,
+    right: &str,
+    output: &str,
+    format: OutputFormat,
+    opts: &CsvReaderOpts,
+    spec: &JoinSpec,
+    convert_opts: &CsvConvertOpts,
+) -> Result<()> {
+    if left == "-" && right == "-" {
+        bail!("Only one of the joined files can be read from stdin");
+    }
+    let left = read_table(opts.open(left)?, opts)?;
+    let right = read_table(opts.open(right)?, opts)?;
+    // values are joined as read, so "1" and "1.0" stay different keys; types are inferred after
+    let mut rows = join_tables(left, right, spec)?;
+    infer_values(&mut rows, convert_opts)?;
+
+    let writer = BufWriter::new(get_writer(output)?);
+    let mut writer = rows_writer(format, writer, convert_opts, &rows);
+    for row in &rows {
+        writer.write_row(row)?;
+    }
+    writer.finish()
+}
+
+fn read_table<R: Read>(mut reader: Reader<R>, opts: &CsvReaderOpts) -> Result<Table> {
+    let headers = read_headers(&mut reader, opts)?;
+    let rows = read_records(reader, opts)?;
+    let columns = match &headers {
+        Some(headers) => headers.iter().map(String::from).collect(),
+        None => rows
+            .first()
+            .and_then(Value::as_object)
+            .map_or_else(Vec::new, |row| row.keys().cloned().collect()),
+    };
+    Ok(Table {
+        headers,
+        columns,
+        rows,
+    })
+}
+
+fn join_tables(left: Table, right: Table, spec: &JoinSpec) -> Result<Vec<Value>> {
+    let right_on = if spec.right_on.is_empty() {
+        &spec.left_on
+    } else {
+        &spec.right_on
+    };
+    if spec.left_on.is_empty() || spec.left_on.len() != right_on.len() {
+        bail!("Expected the same number of key columns for both files");
+    }
+    let left_keys = resolve_keys(&spec.left_on, &left)?;
+    let right_keys = resolve_keys(right_on, &right)?;
+
+    // output names: left keys as is, other columns present on both sides get a suffix
+    let left_rest: HashSet<&str> = left
+        .columns
+        .iter()
+        .filter(|c| !left_keys.contains(c))
+        .map(String::as_str)
+        .collect();
+    let right_rest: Vec<&String> = right
+        .columns
+        .iter()
+        .filter(|c| !right_keys.contains(c))
+        .collect();
+    let left_names: Vec<(&String, String)> = left
+        .columns
+        .iter()
+        .map(|c| {
+            if right_rest.contains(&c) && left_rest.contains(c.as_str()) {
+                (c, format!("{}{}", c, spec.left_suffix))
+            } else {
+                (c, c.clone())
+            }
+        })
+        .collect();
+    let right_names: Vec<(&String, String)> = right_rest
+        .into_iter()
+        .map(|c| {
+            if left.columns.contains(c) {
+                (c, format!("{}{}", c, spec.right_suffix))
+            } else {
+                (c, c.clone())
+            }
+        })
+        .collect();
+
+    let mut index: HashMap<Vec<&str>, Vec<usize>> = HashMap::new();
+    for (i, row) in right.rows.iter().enumerate() {
+        if let Some(key) = row_key(row, &right_keys) {
+            index.entry(key).or_default().push(i);
+        }
+    }
+
+    let keep_left = matches!(spec.kind, JoinKind::Left | JoinKind::Full);
+    let keep_right = matches!(spec.kind, JoinKind::Right | JoinKind::Full);
+    let mut matched = vec![false; right.rows.len()];
+    let mut ret = Vec::new();
+    let pick = |row: Option<&Value>, names: &[(&String, String)], out: &mut Map<String, Value>| {
+        for (column, name) in names {
+            let value = row.and_then(|r| r.get(*column)).cloned();
+            out.insert(name.clone(), value.unwrap_or(Value::Null));
+        }
+    };
+    for row in &left.rows {
+        let matches = row_key(row, &left_keys).and_then(|key| index.get(&key));
+        match matches {
+            Some(matches) => {
+                for &i in matches {
+                    matched[i] = true;
+                    let mut out = Map::new();
+                    pick(Some(row), &left_names, &mut out);
+                    pick(Some(&right.rows[i]), &right_names, &mut out);
+                    ret.push(Value::Object(out));
+                }
+            }
+            None if keep_left => {
+                let mut out = Map::new();
+                pick(Some(row), &left_names, &mut out);
+                pick(None, &right_names, &mut out);
+                ret.push(Value::Object(out));
+            }
+            None => {}
+        }
+    }
+    if keep_right {
+        for (row, _) in right.rows.iter().zip(matched).filter(|(_, m)| !m) {
+            let mut out = Map::new();
+            pick(None, &left_names, &mut out);
+            // rows only found on the right still carry their key in the left key columns
+            for (left_key, right_key) in left_keys.iter().zip(&right_keys) {
+                out.insert(
+                    left_key.clone(),
+                    row.get(right_key).cloned().unwrap_or_default(),
+                );
+            }
+            pick(Some(row), &right_names, &mut out);
+            ret.push(Value::Object(out));
+        }
+    }
+    Ok(ret)
+}
+
+fn resolve_keys(columns: &[String], table: &Table) -> Result<Vec<String>> {
+    columns
+        .iter()
+        .map(|c| resolve_column(c, table.headers.as_ref()))
+        .collect()
+}
+
+// like a SQL join, rows with an empty key never match
+fn row_key<'a>(row: &'a Value, keys: &[String]) -> Option<Vec<&'a str>> {
+    keys.iter()
+        .map(|k| row.get(k).and_then(Value::as_str).filter(|s| !s.is_empty()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn table(data: &str) -> Result<Table> {
+        let opts = CsvReaderOpts::default();
+        read_table(opts.from_reader(data.as_bytes()), &opts)
+    }
+
+    fn join(kind: JoinKind) -> Result<Vec<Value>> {
+        let players = table("id,name,club\n1,Anna,10\n2,Bob,20\n3,Carl,\n")?;
+        let clubs = table("club_id,name\n10,Juve\n30,Milan\n")?;
+        let spec = JoinSpec {
+            kind,
+            left_on: vec!["club".to_string()],
+            right_on: vec!["club_id".to_string()],
+            left_suffix: "_player".to_string(),
+            right_suffix: "_club".to_string(),
+        };
+        join_tables(players, clubs, &spec)
+    }
+
+    #[test]
+    fn test_join_kinds() -> Result<()> {
+        assert_eq!(
+            join(JoinKind::Inner)?,
+            vec![json!({"id": "1", "name_player": "Anna", "club": "10", "name_club": "Juve"})]
+        );
+        let left = join(JoinKind::Left)?;
+        assert_eq!(left.len(), 3);
+        assert_eq!(left[2]["name_club"], Value::Null);
+        assert_eq!(
+            join(JoinKind::Right)?[1],
+            json!({"id": null, "name_player": null, "club": "30", "name_club": "Milan"})
+        );
+        assert_eq!(join(JoinKind::Full)?.len(), 4);
+        Ok(())
+    }
+}
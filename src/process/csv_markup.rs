@@ -0,0 +1,223 @@
+use anyhow::Result;
+use console::{measure_text_width, pad_str, Alignment};
+use serde_json::Value;
+use std::io::Write;
+
+use super::csv_table::{cell_text, numeric_columns, table_columns};
+use crate::RowWriter;
+
+/// Collects every row and writes a GitHub-flavored Markdown table on `finish`.
+pub struct MarkdownWriter<W: Write> {
+    writer: W,
+    rows: Vec<Value>,
+}
+
+/// Collects every row and writes an HTML table on `finish`, as a full page unless `fragment`.
+pub struct HtmlWriter<W: Write> {
+    writer: W,
+    fragment: bool,
+    rows: Vec<Value>,
+}
+
+impl<W: Write> MarkdownWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            rows: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> HtmlWriter<W> {
+    pub fn new(writer: W, fragment: bool) -> Self {
+        Self {
+            writer,
+            fragment,
+            rows: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> RowWriter for MarkdownWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        self.rows.push(row.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer
+            .write_all(render_markdown(&self.rows).as_bytes())?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<W: Write> RowWriter for HtmlWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        self.rows.push(row.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let table = render_html(&self.rows);
+        if self.fragment {
+            self.writer.write_all(table.as_bytes())?;
+        } else {
+            write!(
+                self.writer,
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n</head>\n<body>\n{}</body>\n</html>\n",
+                table
+            )?;
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Render `rows` as a Markdown table padded to column width; numeric columns are right aligned.
+pub fn render_markdown(rows: &[Value]) -> String {
+    let headers = table_columns(rows);
+    if headers.is_empty() {
+        return String::new();
+    }
+    let numeric = numeric_columns(rows, &headers);
+    let header: Vec<String> = headers.iter().map(|h| markdown_text(h)).collect();
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            headers
+                .iter()
+                .map(|h| markdown_text(&cell_text(&row[*h])))
+                .collect()
+        })
+        .collect();
+    // the delimiter row needs at least three dashes
+    let widths: Vec<usize> = header
+        .iter()
+        .enumerate()
+        .map(|(i, h)| {
+            cells
+                .iter()
+                .map(|row| measure_text_width(&row[i]))
+                .fold(measure_text_width(h).max(3), usize::max)
+        })
+        .collect();
+
+    let line = |cells: &[String]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .zip(&numeric)
+            .map(|((cell, w), numeric)| {
+                let align = if *numeric {
+                    Alignment::Right
+                } else {
+                    Alignment::Left
+                };
+                pad_str(cell, *w, align, None).into_owned()
+            })
+            .collect();
+        format!("| {} |\n", padded.join(" | "))
+    };
+    let rule: Vec<String> = widths
+        .iter()
+        .zip(&numeric)
+        .map(|(w, numeric)| match numeric {
+            true => format!("{}:", "-".repeat(w - 1)),
+            false => "-".repeat(*w),
+        })
+        .collect();
+    let mut ret = line(&header);
+    ret.push_str(&line(&rule));
+    for row in &cells {
+        ret.push_str(&line(row));
+    }
+    ret
+}
+
+/// Render `rows` as an HTML `<table>`; numeric columns are right aligned.
+pub fn render_html(rows: &[Value]) -> String {
+    let headers = table_columns(rows);
+    let numeric = numeric_columns(rows, &headers);
+    let attr = |numeric: bool| match numeric {
+        true => " style=\"text-align: right\"",
+        false => "",
+    };
+    let mut ret = String::from("<table>\n<thead>\n<tr>\n");
+    for (h, numeric) in headers.iter().zip(&numeric) {
+        ret.push_str(&format!("<th{}>{}</th>\n", attr(*numeric), escape_html(h)));
+    }
+    ret.push_str("</tr>\n</thead>\n<tbody>\n");
+    for row in rows {
+        ret.push_str("<tr>\n");
+        for (h, numeric) in headers.iter().zip(&numeric) {
+            let text = escape_html(&cell_text(&row[*h]));
+            ret.push_str(&format!("<td{}>{}</td>\n", attr(*numeric), text));
+        }
+        ret.push_str("</tr>\n");
+    }
+    ret.push_str("</tbody>\n</table>\n");
+    ret
+}
+
+// a cell must stay on one line, must not close the column early and must not be read as
+// inline html by markdown viewers
+fn markdown_text(text: &str) -> String {
+    escape_html(text)
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\r', '\n'], "<br>")
+}
+
+fn escape_html(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_markdown() {
+        let rows = vec![
+            json!({"name": "Buffon", "age": 40, "note": "a|b"}),
+            json!({"name": "Dybala", "age": 9, "note": "line\nbreak"}),
+        ];
+        assert_eq!(
+            render_markdown(&rows),
+            "| name   | age | note          |\n\
+             | ------ | --: | ------------- |\n\
+             | Buffon |  40 | a\\|b          |\n\
+             | Dybala |   9 | line<br>break |\n"
+        );
+        let rows = vec![json!({"note": "<img onerror=x> & <script>"})];
+        assert_eq!(
+            render_markdown(&rows),
+            "| note                                       |\n\
+             | ------------------------------------------ |\n\
+             | &lt;img onerror=x&gt; &amp; &lt;script&gt; |\n"
+        );
+    }
+
+    #[test]
+    fn test_render_html() {
+        let rows = vec![json!({"name": "<b>Tom & \"Jerry\"</b>", "age": 3})];
+        assert_eq!(
+            render_html(&rows),
+            "<table>\n<thead>\n<tr>\n<th>name</th>\n<th style=\"text-align: right\">age</th>\n\
+             </tr>\n</thead>\n<tbody>\n<tr>\n\
+             <td>&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;</td>\n\
+             <td style=\"text-align: right\">3</td>\n</tr>\n</tbody>\n</table>\n"
+        );
+    }
+}
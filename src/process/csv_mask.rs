@@ -0,0 +1,273 @@
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use csv::StringRecord;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde_json::{Number, Value};
+use std::str::FromStr;
+
+use super::{
+    csv_select::resolve_column,
+    text::{Blake3, TextSigner},
+};
+use crate::RowWriter;
+
+const REDACTED: &str = "REDACTED";
+const FIRST_NAMES: [&str; 16] = [
+    "Alex", "Blair", "Casey", "Dana", "Eli", "Frankie", "Gray", "Harper", "Jordan", "Kai", "Logan",
+    "Morgan", "Quinn", "Riley", "Sam", "Taylor",
+];
+const LAST_NAMES: [&str; 16] = [
+    "Adams", "Baker", "Clark", "Davis", "Evans", "Fisher", "Garcia", "Hill", "Jones", "King",
+    "Lopez", "Moore", "Nguyen", "Parker", "Reed", "Smith",
+];
+
+/// A `--mask` rule, `column=strategy`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskRule {
+    pub column: String,
+    pub strategy: MaskStrategy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskStrategy {
+    Redact,
+    // keyed blake3 of the value, so equal values can still be matched
+    Hash,
+    // keep this many trailing characters, e.g. `****1234`
+    Partial(usize),
+    // a made up value chosen by the hash of the real one, so the same input gets the same fake
+    Fake(FakeKind),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FakeKind {
+    Name,
+    Email,
+    Phone,
+    // the same characters with every digit replaced
+    Number,
+}
+
+/// Replaces the values of masked columns before passing rows on.
+pub struct MaskWriter<'a> {
+    inner: Box<dyn RowWriter + 'a>,
+    rules: Vec<MaskRule>,
+    masker: Masker,
+}
+
+struct Masker {
+    signer: Option<Blake3>,
+}
+
+impl FromStr for MaskRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((column, strategy)) = s.rsplit_once('=').filter(|(c, _)| !c.is_empty()) else {
+            bail!("Expected column=strategy");
+        };
+        let strategy = match strategy.split_once(':') {
+            None if strategy == "redact" => MaskStrategy::Redact,
+            None if strategy == "hash" => MaskStrategy::Hash,
+            None if strategy == "partial" => MaskStrategy::Partial(4),
+            Some(("partial", n)) if n.parse::<usize>().is_ok() => MaskStrategy::Partial(n.parse()?),
+            Some(("fake", "name")) => MaskStrategy::Fake(FakeKind::Name),
+            Some(("fake", "email")) => MaskStrategy::Fake(FakeKind::Email),
+            Some(("fake", "phone")) => MaskStrategy::Fake(FakeKind::Phone),
+            Some(("fake", "number")) => MaskStrategy::Fake(FakeKind::Number),
+            _ => bail!(
+                "Unsupported mask strategy: {}, expected redact, hash, partial[:N] or \
+                 fake:name|email|phone|number",
+                strategy
+            ),
+        };
+        Ok(MaskRule {
+            column: column.to_string(),
+            strategy,
+        })
+    }
+}
+
+impl<'a> MaskWriter<'a> {
+    /// `key` is a blake3 key file's content, needed by the hash and fake strategies.
+    pub fn new(
+        inner: Box<dyn RowWriter + 'a>,
+        rules: &[MaskRule],
+        key: Option<&[u8]>,
+        headers: Option<&StringRecord>,
+    ) -> Result<Self> {
+        let rules: Vec<MaskRule> = rules
+            .iter()
+            .map(|rule| {
+                Ok(MaskRule {
+                    column: resolve_column(&rule.column, headers)?,
+                    strategy: rule.strategy,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let signer = match key {
+            Some(key) if key.len() < 32 => bail!("Mask key must be at least 32 bytes"),
+            Some(key) => Some(Blake3::try_new(key)?),
+            None if needs_key(&rules) => bail!("The hash and fake mask strategies need --mask-key"),
+            None => None,
+        };
+        Ok(Self {
+            inner,
+            rules,
+            masker: Masker { signer },
+        })
+    }
+}
+
+// unkeyed, hashes and fakes could be reversed by hashing guessed inputs
+fn needs_key(rules: &[MaskRule]) -> bool {
+    rules
+        .iter()
+        .any(|r| matches!(r.strategy, MaskStrategy::Hash | MaskStrategy::Fake(_)))
+}
+
+impl RowWriter for MaskWriter<'_> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        let mut row = row.clone();
+        if let Value::Object(map) = &mut row {
+            for rule in &self.rules {
+                if let Some(value) = map.get_mut(&rule.column) {
+                    *value = self.masker.mask(value, rule.strategy)?;
+                }
+            }
+        }
+        self.inner.write_row(&row)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+
+    fn done(&self) -> bool {
+        self.inner.done()
+    }
+}
+
+impl Masker {
+    // missing and empty values are kept, there is nothing to hide
+    fn mask(&self, value: &Value, strategy: MaskStrategy) -> Result<Value> {
+        let text = match value {
+            Value::Null => return Ok(Value::Null),
+            Value::String(s) if s.is_empty() => return Ok(value.clone()),
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        };
+        let masked = match strategy {
+            MaskStrategy::Redact => REDACTED.to_string(),
+            MaskStrategy::Hash => URL_SAFE_NO_PAD.encode(self.digest(&text)?),
+            MaskStrategy::Partial(keep) => {
+                let chars: Vec<char> = text.chars().collect();
+                let tail: String = match chars.len() > keep {
+                    true => chars[chars.len() - keep..].iter().collect(),
+                    false => String::new(),
+                };
+                format!("****{}", tail)
+            }
+            MaskStrategy::Fake(kind) => {
+                let mut rng = StdRng::from_seed(self.digest(&text)?);
+                let fake = fake_value(kind, &text, &mut rng);
+                // numbers stay numbers so typed output keeps its schema
+                if kind == FakeKind::Number && value.is_number() {
+                    if let Ok(n) = fake.parse::<Number>() {
+                        return Ok(Value::Number(n));
+                    }
+                }
+                fake
+            }
+        };
+        Ok(Value::String(masked))
+    }
+
+    fn digest(&self, text: &str) -> Result<[u8; 32]> {
+        let signer = self
+            .signer
+            .as_ref()
+            .ok_or_else(|| anyhow!("Masking needs --mask-key"))?;
+        signer
+            .sign(&mut text.as_bytes())?
+            .try_into()
+            .map_err(|_| anyhow!("Unexpected blake3 digest length"))
+    }
+}
+
+fn fake_value(kind: FakeKind, text: &str, rng: &mut StdRng) -> String {
+    let first = FIRST_NAMES.choose(rng).unwrap();
+    let last = LAST_NAMES.choose(rng).unwrap();
+    match kind {
+        FakeKind::Name => format!("{} {}", first, last),
+        FakeKind::Email => format!(
+            "{}.{}{}@example.com",
+            first.to_lowercase(),
+            last.to_lowercase(),
+            rng.gen_range(1..1000)
+        ),
+        FakeKind::Phone => format!(
+            "+1 555-{:03}-{:04}",
+            rng.gen_range(100..1000),
+            rng.gen_range(0..10000)
+        ),
+        FakeKind::Number => {
+            let mut leading = true;
+            text.chars()
+                .map(|c| match c.to_digit(10) {
+                    // keep the first digit non-zero so the result stays the same size
+                    Some(_) if leading => {
+                        leading = false;
+                        char::from(b'0' + rng.gen_range(1..10))
+                    }
+                    Some(_) => char::from(b'0' + rng.gen_range(0..10)),
+                    None => c,
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_mask_rule() -> Result<()> {
+        let rule: MaskRule = "card=partial:2".parse()?;
+        assert_eq!(rule.strategy, MaskStrategy::Partial(2));
+        let rule: MaskRule = "a=b=fake:email".parse()?;
+        assert_eq!(rule.column, "a=b");
+        assert_eq!(rule.strategy, MaskStrategy::Fake(FakeKind::Email));
+        assert!("card".parse::<MaskRule>().is_err());
+        assert!("card=fake".parse::<MaskRule>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mask_writer() -> Result<()> {
+        let rules: Vec<MaskRule> = ["name=fake:name", "card=partial", "ssn=redact", "id=hash"]
+            .iter()
+            .map(|r| r.parse())
+            .collect::<Result<_>>()?;
+        let key = include_bytes!("../../fixtures/blake3.txt");
+        let mut rows = Vec::new();
+        let mut writer = MaskWriter::new(Box::new(&mut rows), &rules, Some(key), None)?;
+        let row = json!({"name": "Anna", "card": "4111111111111234", "ssn": "", "id": 42});
+        writer.write_row(&row)?;
+        writer.write_row(&row)?;
+        drop(writer);
+
+        assert_eq!(rows[0], rows[1]);
+        assert_eq!(rows[0]["card"], "****1234");
+        assert_eq!(rows[0]["ssn"], "");
+        assert_ne!(rows[0]["name"], "Anna");
+        assert_eq!(rows[0]["id"].as_str().map(str::len), Some(43));
+
+        // fakes and hashes need the key, redact and partial do not
+        assert!(MaskWriter::new(Box::new(&mut rows), &rules[..1], None, None).is_err());
+        assert!(MaskWriter::new(Box::new(&mut rows), &rules[1..3], None, None).is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+use crate::RowWriter;
+
+/// Turns dotted (`address.city`) and indexed (`tags[0]`) keys into nested objects and arrays.
+pub struct NestWriter<'a> {
+    inner: Box<dyn RowWriter + 'a>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl<'a> NestWriter<'a> {
+    pub fn new(inner: Box<dyn RowWriter + 'a>) -> Self {
+        Self { inner }
+    }
+}
+
+impl RowWriter for NestWriter<'_> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        self.inner.write_row(&nest_row(row.clone())?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+
+    fn done(&self) -> bool {
+        self.inner.done()
+    }
+}
+
+/// Nest the keys of a flat row; trailing empty cells are dropped from arrays.
+pub fn nest_row(row: Value) -> Result<Value> {
+    let Value::Object(map) = row else {
+        return Ok(row);
+    };
+    let mut ret = Value::Object(Map::new());
+    for (key, value) in map {
+        // keys that do not parse as a path are kept as they are
+        let path = parse_path(&key).unwrap_or_else(|| vec![Segment::Key(key.clone())]);
+        if !insert(&mut ret, &path, value) {
+            bail!("Column {:?} conflicts with another nested column", key);
+        }
+    }
+    trim_arrays(&mut ret);
+    Ok(ret)
+}
+
+fn parse_path(key: &str) -> Option<Vec<Segment>> {
+    let mut path = Vec::new();
+    for part in key.split('.') {
+        let (name, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if name.is_empty() {
+            return None;
+        }
+        path.push(Segment::Key(name.to_string()));
+        while !rest.is_empty() {
+            let (index, tail) = rest.strip_prefix('[')?.split_once(']')?;
+            path.push(Segment::Index(index.parse().ok()?));
+            rest = tail;
+        }
+    }
+    Some(path)
+}
+
+// returns false when the path runs into a value of a different shape
+fn insert(target: &mut Value, path: &[Segment], value: Value) -> bool {
+    let Some((first, rest)) = path.split_first() else {
+        if !target.is_null() {
+            return false;
+        }
+        *target = value;
+        return true;
+    };
+    match first {
+        Segment::Key(key) => {
+            if target.is_null() {
+                *target = Value::Object(Map::new());
+            }
+            let Value::Object(map) = target else {
+                return false;
+            };
+            insert(map.entry(key.clone()).or_insert(Value::Null), rest, value)
+        }
+        Segment::Index(i) => {
+            if target.is_null() {
+                *target = Value::Array(Vec::new());
+            }
+            let Value::Array(items) = target else {
+                return false;
+            };
+            if items.len() <= *i {
+                items.resize(*i + 1, Value::Null);
+            }
+            insert(&mut items[*i], rest, value)
+        }
+    }
+}
+
+// spreadsheets reserve a column per possible element, so shorter lists leave empty cells behind
+fn trim_arrays(value: &mut Value) {
+    match value {
+        Value::Array(items) => {
+            while items.last().is_some_and(|v| v.is_null() || v == "") {
+                items.pop();
+            }
+            items.iter_mut().for_each(trim_arrays);
+        }
+        Value::Object(map) => map.values_mut().for_each(trim_arrays),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_nest_row() -> Result<()> {
+        let row = json!({
+            "name": "Anna",
+            "address.city": "Berlin",
+            "address.zip": 10115,
+            "tags[0]": "a",
+            "tags[1]": "",
+            "tags[2]": null,
+            "jobs[0].title": "dev",
+            "a..b": 1,
+        });
+        assert_eq!(
+            nest_row(row)?,
+            json!({
+                "name": "Anna",
+                "address": {"city": "Berlin", "zip": 10115},
+                "tags": ["a"],
+                "jobs": [{"title": "dev"}],
+                "a..b": 1,
+            })
+        );
+        assert!(nest_row(json!({"a": 1, "a.b": 2})).is_err());
+        Ok(())
+    }
+}
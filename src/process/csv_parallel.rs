@@ -0,0 +1,396 @@
+use anyhow::Result;
+use csv::StringRecord;
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use serde_json::Value;
+use std::io::Read;
+
+use super::csv_convert::{read_headers, record_to_value};
+use crate::{BadRow, BadRowPolicy, CsvReaderOpts, RecordReader};
+
+// bytes of input each worker parses per batch
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Converts each row after parsing, on the worker that parsed it.
+pub type RowMap<'a> = &'a (dyn Fn(&mut Value) -> Result<()> + Sync);
+
+/// Reads rows one at a time, parsing the input on a thread pool when `threads` allows it.
+pub enum RowReader {
+    Serial {
+        records: Box<RecordReader<Box<dyn Read>>>,
+        headers: Option<StringRecord>,
+        record: StringRecord,
+    },
+    Parallel {
+        reader: ParallelReader,
+        batch: std::vec::IntoIter<Value>,
+    },
+}
+
+impl RowReader {
+    /// Open `input`, skipping bad rows serially so the rejects keep their input order.
+    pub fn open(
+        input: &str,
+        opts: &CsvReaderOpts,
+        threads: usize,
+        policy: &BadRowPolicy,
+    ) -> Result<Self> {
+        if threads != 1 && *policy == BadRowPolicy::Fail {
+            let reader = ParallelReader::new(opts.open_raw(input)?, opts, threads)?;
+            return Ok(Self::Parallel {
+                reader,
+                batch: Vec::new().into_iter(),
+            });
+        }
+        let mut reader = opts.open(input)?;
+        let headers = read_headers(&mut reader, opts)?;
+        Ok(Self::Serial {
+            records: Box::new(RecordReader::new(reader, opts, policy)?),
+            headers,
+            record: StringRecord::new(),
+        })
+    }
+
+    pub fn headers(&self) -> Option<&StringRecord> {
+        match self {
+            Self::Serial { headers, .. } => headers.as_ref(),
+            Self::Parallel { reader, .. } => reader.headers(),
+        }
+    }
+
+    pub fn next_row(&mut self, map: RowMap) -> Result<Option<Value>> {
+        match self {
+            Self::Serial {
+                records,
+                headers,
+                record,
+            } => {
+                if !records.read_record(record)? {
+                    return Ok(None);
+                }
+                let mut row = record_to_value(headers.as_ref(), record);
+                map(&mut row)?;
+                Ok(Some(row))
+            }
+            Self::Parallel { reader, batch } => loop {
+                if let Some(row) = batch.next() {
+                    return Ok(Some(row));
+                }
+                match reader.next_batch(map)? {
+                    Some(rows) => *batch = rows.into_iter(),
+                    None => return Ok(None),
+                }
+            },
+        }
+    }
+
+    /// Bytes of input read so far, for progress reporting.
+    pub fn byte(&self) -> u64 {
+        match self {
+            Self::Serial { records, .. } => records.position().byte(),
+            Self::Parallel { reader, .. } => reader.byte,
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        match self {
+            Self::Serial { records, .. } => records.finish(),
+            Self::Parallel { .. } => Ok(()),
+        }
+    }
+}
+
+/// Splits the input at record boundaries and parses the chunks on a thread pool.
+pub struct ParallelReader {
+    reader: Box<dyn Read>,
+    parser: ChunkParser,
+    pool: ThreadPool,
+    threads: usize,
+    chunk_size: usize,
+    buf: Vec<u8>,
+    eof: bool,
+    // line and byte of the start of `buf` in the input
+    line: u64,
+    byte: u64,
+}
+
+impl ParallelReader {
+    /// `threads` of 0 uses every core.
+    pub fn new(reader: Box<dyn Read>, opts: &CsvReaderOpts, threads: usize) -> Result<Self> {
+        Self::with_chunk_size(reader, opts, threads, CHUNK_SIZE)
+    }
+
+    fn with_chunk_size(
+        reader: Box<dyn Read>,
+        opts: &CsvReaderOpts,
+        threads: usize,
+        chunk_size: usize,
+    ) -> Result<Self> {
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+        let parser = ChunkParser {
+            opts: opts.clone(),
+            headers: None,
+            first: Vec::new(),
+        };
+        let mut ret = Self {
+            reader,
+            parser,
+            threads: pool.current_num_threads(),
+            pool,
+            chunk_size,
+            buf: Vec::new(),
+            eof: false,
+            line: 1,
+            byte: 0,
+        };
+        let end = match ret.fill_until(|buf, eof| chunk_ends(buf, 1, opts, eof).first().copied())? {
+            Some(end) => end,
+            None => {
+                // empty input, like the csv reader it still has an empty header
+                ret.parser.headers = opts.has_headers.then(StringRecord::new);
+                return Ok(ret);
+            }
+        };
+        let mut first = ret.buf[..end].to_vec();
+        if !first.ends_with(b"\n") {
+            first.push(b'\n');
+        }
+        if opts.has_headers {
+            ret.parser.headers = read_headers(&mut opts.from_reader(first.as_slice()), opts)?;
+            ret.consume(end);
+        }
+        ret.parser.first = first;
+        Ok(ret)
+    }
+
+    pub fn headers(&self) -> Option<&StringRecord> {
+        self.parser.headers.as_ref()
+    }
+
+    /// Parse the next batch of rows, in input order, or `None` at the end of the input.
+    pub fn next_batch(&mut self, map: RowMap) -> Result<Option<Vec<Value>>> {
+        let target = self.chunk_size;
+        let opts = self.parser.opts.clone();
+        let threads = self.threads;
+        let cuts = self.fill_until(|buf, eof| {
+            let cuts = chunk_ends(buf, target, &opts, eof);
+            // wait for a full batch unless the input has run out
+            (cuts.len() >= threads || eof && !cuts.is_empty()).then_some(cuts)
+        })?;
+        let Some(cuts) = cuts else {
+            return Ok(None);
+        };
+
+        let mut chunks = Vec::with_capacity(cuts.len());
+        let (mut start, mut line, mut byte) = (0, self.line, self.byte);
+        for &end in &cuts {
+            let data = &self.buf[start..end];
+            chunks.push((data, line, byte));
+            line += count_lines(data);
+            byte += data.len() as u64;
+            start = end;
+        }
+        let parser = &self.parser;
+        let batches = self.pool.install(|| {
+            chunks
+                .par_iter()
+                .map(|&(data, line, byte)| parser.parse(data, line, byte, map))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        self.consume(start);
+        Ok(Some(batches.into_iter().flatten().collect()))
+    }
+
+    // read more input until `done` finds what it needs, returns `None` at the end of the input
+    fn fill_until<T>(&mut self, done: impl Fn(&[u8], bool) -> Option<T>) -> Result<Option<T>> {
+        let mut want = self.buf.len() + self.chunk_size * self.threads;
+        loop {
+            while !self.eof && self.buf.len() < want {
+                let len = self.buf.len();
+                self.buf.resize(want, 0);
+                let n = self.reader.read(&mut self.buf[len..])?;
+                self.buf.truncate(len + n);
+                self.eof = n == 0;
+            }
+            if let Some(ret) = done(&self.buf, self.eof) {
+                return Ok(Some(ret));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            // a record longer than the buffer, keep reading
+            want *= 2;
+        }
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.line += count_lines(&self.buf[..len]);
+        self.byte += len as u64;
+        self.buf.drain(..len);
+    }
+}
+
+// the parts of the reader the workers share
+struct ChunkParser {
+    opts: CsvReaderOpts,
+    headers: Option<StringRecord>,
+    // the first record, parsed ahead of every chunk so field counts are checked against it
+    first: Vec<u8>,
+}
+
+impl ChunkParser {
+    fn parse(&self, data: &[u8], line: u64, byte: u64, map: RowMap) -> Result<Vec<Value>> {
+        let reader = self
+            .opts
+            .builder()
+            .has_headers(true)
+            .from_reader(self.first.chain(data));
+        let mut records = RecordReader::new(reader, &self.opts, &BadRowPolicy::Fail)?;
+        let mut rows = Vec::new();
+        let mut record = StringRecord::new();
+        loop {
+            match records.read_record(&mut record) {
+                Ok(true) => {
+                    let mut row = record_to_value(self.headers.as_ref(), &record);
+                    map(&mut row)?;
+                    rows.push(row);
+                }
+                Ok(false) => return Ok(rows),
+                Err(mut e) => {
+                    // positions are relative to the first record prepended to the chunk, and
+                    // lines are counted here so a lone \r ends one too
+                    if let Some(bad) = e.downcast_mut::<BadRow>() {
+                        let offset = (bad.byte as usize).saturating_sub(self.first.len());
+                        bad.line = line + count_lines(&data[..offset.min(data.len())]);
+                        bad.byte = byte + offset as u64;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+// lines end with \n, \r or \r\n, like records in the csv parser
+fn count_lines(data: &[u8]) -> u64 {
+    data.iter()
+        .enumerate()
+        .filter(|&(i, &b)| b == b'\n' || b == b'\r' && data.get(i + 1) != Some(&b'\n'))
+        .count() as u64
+}
+
+// Offsets just past the records that end chunks of at least `target` bytes. Quotes only open at
+// the start of a field, like the csv parser, so line breaks inside quoted fields are skipped;
+// blank and comment lines never end a chunk. `\r\n` is a single break, so a chunk never ends
+// between the two. At the end of the input the rest is the last chunk.
+fn chunk_ends(data: &[u8], target: usize, opts: &CsvReaderOpts, eof: bool) -> Vec<usize> {
+    let mut ends = Vec::new();
+    let (mut start, mut i) = (0, 0);
+    let (mut quoted, mut field_start, mut line_start, mut empty) = (false, true, true, true);
+    while i < data.len() {
+        let b = data[i];
+        if quoted {
+            if Some(b) == opts.escape {
+                i += 1;
+            } else if b == opts.quote {
+                // a doubled quote stays inside the field
+                if opts.escape.is_none() && data.get(i + 1) == Some(&opts.quote) {
+                    i += 1;
+                } else {
+                    quoted = false;
+                }
+            }
+        } else if line_start && Some(b) == opts.comment {
+            // the csv parser only ends comments at \n
+            match data[i..].iter().position(|&b| b == b'\n') {
+                Some(n) => i += n,
+                None => break,
+            }
+            continue;
+        } else if b == b'\n' || b == b'\r' {
+            if b == b'\r' {
+                match data.get(i + 1) {
+                    Some(b'\n') => {
+                        i += 1;
+                        continue;
+                    }
+                    // the next read may start with the \n
+                    None if !eof => break,
+                    _ => {}
+                }
+            }
+            if !empty && i + 1 - start >= target {
+                ends.push(i + 1);
+                start = i + 1;
+            }
+            (field_start, line_start, empty) = (true, true, true);
+            i += 1;
+            continue;
+        } else if b == opts.quote && field_start {
+            quoted = true;
+        } else {
+            field_start = b == opts.delimiter;
+        }
+        empty = false;
+        line_start = false;
+        i += 1;
+    }
+    if eof && start < data.len() {
+        ends.push(data.len());
+    }
+    ends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::csv_convert::read_records;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parallel_reader_matches_serial() -> Result<()> {
+        let data = "id,note\n1,plain\n2,\"multi\nline, \"\"quoted\"\"\"\n\n3,\"x\"\n# c\n4,last";
+        // the csv parser also ends records with \r\n and a lone \r, though comments only at \n
+        let cr = data.replace("# c\n", "").replace('\n', "\r");
+        for data in [data.to_string(), data.replace('\n', "\r\n"), cr] {
+            for has_headers in [true, false] {
+                let opts = CsvReaderOpts {
+                    comment: Some(b'#'),
+                    has_headers,
+                    ..Default::default()
+                };
+                let expected = read_records(opts.from_reader(data.as_bytes()), &opts)?;
+                let mut reader = ParallelReader::with_chunk_size(
+                    Box::new(Cursor::new(data.clone())),
+                    &opts,
+                    2,
+                    4,
+                )?;
+                let mut rows = Vec::new();
+                while let Some(batch) = reader.next_batch(&|_| Ok(()))? {
+                    rows.extend(batch);
+                }
+                assert_eq!(rows, expected);
+                assert_eq!(chunk_ends(data.as_bytes(), 4, &opts, true).len(), 5);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_reader_error_position() -> Result<()> {
+        let data = "a,b\n1,2\n3,4\n5,6\n7\n";
+        let opts = CsvReaderOpts::default();
+        for data in [data.to_string(), data.replace('\n', "\r")] {
+            let mut reader =
+                ParallelReader::with_chunk_size(Box::new(Cursor::new(data.clone())), &opts, 2, 4)?;
+            let err = loop {
+                if let Err(e) = reader.next_batch(&|_| Ok(())) {
+                    break e;
+                }
+            };
+            let bad = err.downcast_ref::<BadRow>().unwrap();
+            assert_eq!((bad.line, bad.byte), (5, 16));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::{anyhow, bail, Result};
+use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use serde_json::Value;
+use std::{collections::HashSet, io::Write, sync::Arc};
+
+use crate::{BatchSchema, RowWriter};
+
+// rows buffered per record batch; unless the schema is given up front, the first batch decides it
+const BATCH_SIZE: usize = 8192;
+
+pub struct ParquetRowWriter<W: Write + Send> {
+    writer: Option<W>,
+    arrow: Option<ArrowWriter<W>>,
+    schema: Option<SchemaRef>,
+    rows: Vec<Value>,
+}
+
+impl<W: Write + Send> ParquetRowWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Some(writer),
+            arrow: None,
+            schema: None,
+            rows: Vec::with_capacity(BATCH_SIZE),
+        }
+    }
+
+    fn flush_batch(&mut self) -> Result<()> {
+        if self.arrow.is_none() {
+            let schema = self
+                .schema
+                .get_or_insert_with(|| infer_schema(&self.rows))
+                .clone();
+            let props = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let writer = self.writer.take().expect("writer is only taken once");
+            self.arrow = Some(ArrowWriter::try_new(writer, schema, Some(props))?);
+        }
+        if let (Some(arrow), Some(schema)) = (self.arrow.as_mut(), self.schema.as_ref()) {
+            if !self.rows.is_empty() {
+                arrow.write(&to_record_batch(schema, &self.rows)?)?;
+            }
+        }
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> BatchSchema for ParquetRowWriter<W> {
+    fn with_rows(mut self, rows: &[Value]) -> Self {
+        self.schema = Some(infer_schema(rows));
+        self
+    }
+}
+
+impl<W: Write + Send> RowWriter for ParquetRowWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        self.rows.push(row.clone());
+        if self.rows.len() >= BATCH_SIZE {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush_batch()?;
+        if let Some(arrow) = self.arrow.as_mut() {
+            arrow.finish()?;
+            arrow.inner_mut().flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn infer_schema(rows: &[Value]) -> SchemaRef {
+    let mut seen = HashSet::new();
+    let mut columns: Vec<(&str, Option<DataType>)> = Vec::new();
+    for (key, value) in rows.iter().filter_map(Value::as_object).flatten() {
+        if seen.insert(key.as_str()) {
+            columns.push((key.as_str(), None));
+        }
+        let ty = match value {
+            Value::Null => continue,
+            Value::Bool(_) => DataType::Boolean,
+            Value::Number(n) if n.is_i64() => DataType::Int64,
+            Value::Number(_) => DataType::Float64,
+            _ => DataType::Utf8,
+        };
+        let column = columns
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .expect("column seen");
+        column.1 = Some(match column.1.take() {
+            None => ty,
+            Some(prev) if prev == ty => ty,
+            Some(DataType::Int64 | DataType::Float64)
+                if matches!(ty, DataType::Int64 | DataType::Float64) =>
+            {
+                DataType::Float64
+            }
+            Some(_) => DataType::Utf8,
+        });
+    }
+    let fields: Vec<Field> = columns
+        .into_iter()
+        .map(|(name, ty)| Field::new(name, ty.unwrap_or(DataType::Utf8), true))
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn to_record_batch(schema: &SchemaRef, rows: &[Value]) -> Result<RecordBatch> {
+    // a column the schema has no field for would be silently dropped
+    for key in rows
+        .iter()
+        .filter_map(Value::as_object)
+        .flat_map(|row| row.keys())
+    {
+        if schema.field_with_name(key).is_err() {
+            bail!(
+                "column {:?} is not in the first {} rows that decided the parquet schema",
+                key,
+                BATCH_SIZE
+            );
+        }
+    }
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(schema.fields().len());
+    for field in schema.fields() {
+        let name = field.name();
+        let values = rows
+            .iter()
+            .map(|row| row.get(name).filter(|v| !v.is_null()));
+        let mismatch = |v: &Value| {
+            anyhow!(
+                "column {:?}: {} does not fit parquet type {}, try --infer column",
+                name,
+                v,
+                field.data_type()
+            )
+        };
+        let array: ArrayRef = match field.data_type() {
+            DataType::Boolean => Arc::new(
+                values
+                    .map(|v| {
+                        v.map(|v| v.as_bool().ok_or_else(|| mismatch(v)))
+                            .transpose()
+                    })
+                    .collect::<Result<BooleanArray>>()?,
+            ),
+            DataType::Int64 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_i64().ok_or_else(|| mismatch(v))).transpose())
+                    .collect::<Result<Int64Array>>()?,
+            ),
+            DataType::Float64 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_f64().ok_or_else(|| mismatch(v))).transpose())
+                    .collect::<Result<Float64Array>>()?,
+            ),
+            _ => Arc::new(
+                values
+                    .map(|v| {
+                        v.map(|v| match v {
+                            Value::String(s) => s.clone(),
+                            v => v.to_string(),
+                        })
+                    })
+                    .collect::<StringArray>(),
+            ),
+        };
+        columns.push(array);
+    }
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+    use std::fs::File;
+
+    #[test]
+    fn test_parquet_row_writer() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out.parquet");
+        let mut writer = ParquetRowWriter::new(File::create(&path)?);
+        writer.write_row(&json!({"name": "Anna", "age": 30, "score": 1, "ok": true}))?;
+        writer.write_row(&json!({"name": null, "age": 41, "score": 2.5, "ok": null}))?;
+        writer.finish()?;
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?;
+        let types: Vec<_> = reader
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.data_type().clone())
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                DataType::Utf8,
+                DataType::Int64,
+                DataType::Float64,
+                DataType::Boolean
+            ]
+        );
+        let rows: usize = reader
+            .build()?
+            .map(|b| b.map(|b| b.num_rows()))
+            .sum::<Result<_, _>>()?;
+        assert_eq!(rows, 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{bail, Result};
+use rusqlite::{types::ValueRef, Connection};
+use serde_json::{Map, Number, Value};
+use std::io::BufWriter;
+
+use super::{
+    csv_convert::{read_headers, read_records},
+    csv_sql::load_table,
+};
+use crate::{
+    cli::OutputFormat, get_writer, infer_values, rows_writer, CsvConvertOpts, CsvReaderOpts,
+};
+
+/// Load each `(name, file)` of `tables` into an in-memory SQLite database, run `sql` and write
+/// the result rows in `format`.
+pub fn process_csv_query(
+    sql: &str,
+    tables: &[(String, String)],
+    output: &str,
+    format: OutputFormat,
+    opts: &CsvReaderOpts,
+    convert_opts: &CsvConvertOpts,
+) -> Result<()> {
+    if tables.iter().filter(|(_, input)| input == "-").count() > 1 {
+        bail!("Only one of the queried files can be read from stdin");
+    }
+    let conn = Connection::open_in_memory()?;
+    for (name, input) in tables {
+        let mut reader = opts.open(input)?;
+        let headers: Vec<String> = match read_headers(&mut reader, opts)? {
+            Some(headers) => headers.iter().map(String::from).collect(),
+            None => Vec::new(),
+        };
+        let mut rows = read_records(reader, opts)?;
+        // typed columns make numeric comparisons and aggregates work as expected
+        infer_values(&mut rows, convert_opts)?;
+        load_table(&conn, name, &headers, &rows)?;
+    }
+
+    let mut stmt = conn.prepare(sql)?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let mut result = Vec::new();
+    let mut query = stmt.query([])?;
+    while let Some(row) = query.next()? {
+        let mut map = Map::with_capacity(names.len());
+        for (i, name) in names.iter().enumerate() {
+            map.insert(name.clone(), to_json(row.get_ref(i)?));
+        }
+        result.push(Value::Object(map));
+    }
+
+    // the whole result decides the SQL table and parquet schema, not its first batch
+    let writer = BufWriter::new(get_writer(output)?);
+    let mut writer = rows_writer(format, writer, convert_opts, &result);
+    for row in &result {
+        writer.write_row(row)?;
+    }
+    writer.finish()
+}
+
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        ValueRef::Text(s) | ValueRef::Blob(s) => Value::from(String::from_utf8_lossy(s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_query() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        fs::write(
+            path("players.csv"),
+            "name,team,age\nAnna,a,30\nBob,b,20\nCem,a,40\n",
+        )?;
+        fs::write(path("teams.csv"), "team,city\na,Turin\nb,Milan\n")?;
+        let tables = [
+            ("players".to_string(), path("players.csv")),
+            ("teams".to_string(), path("teams.csv")),
+        ];
+        let sql = "SELECT city, avg(age) AS age FROM players JOIN teams USING (team) \
+                   GROUP BY city ORDER BY city";
+        let opts = CsvReaderOpts::default();
+        let convert_opts = CsvConvertOpts::default();
+        let output = path("out.json");
+        process_csv_query(
+            sql,
+            &tables,
+            &output,
+            OutputFormat::Ndjson,
+            &opts,
+            &convert_opts,
+        )?;
+        assert_eq!(
+            fs::read_to_string(output)?,
+            "{\"city\":\"Milan\",\"age\":20.0}\n{\"city\":\"Turin\",\"age\":35.0}\n"
+        );
+
+        // a float after the first batch of integers widens the column
+        let convert_opts = CsvConvertOpts {
+            sql_batch_size: 1,
+            ..Default::default()
+        };
+        let sql = "SELECT iif(age = 20, age, age / 20.0) AS n FROM players ORDER BY age";
+        let output = path("out.sql");
+        process_csv_query(
+            sql,
+            &tables,
+            &output,
+            OutputFormat::Sql,
+            &opts,
+            &convert_opts,
+        )?;
+        let sql = fs::read_to_string(output)?;
+        assert!(sql.contains("\"n\" DOUBLE PRECISION"));
+        assert!(sql.contains("(1.5)"));
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+use crate::RowWriter;
+
+/// Column selection as given on the command line; columns are header names or 1-based indexes.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnSelection {
+    pub select: Vec<String>,
+    pub exclude: Vec<String>,
+    pub rename: Vec<(String, String)>,
+    pub order: Vec<String>,
+}
+
+/// A `ColumnSelection` resolved against the actual header names.
+#[derive(Debug, Clone, Default)]
+pub struct Projection {
+    select: Option<Vec<String>>,
+    exclude: HashSet<String>,
+    order: Vec<String>,
+    rename: HashMap<String, String>,
+}
+
+/// Applies a `Projection` to every row before passing it on.
+pub struct ProjectWriter<'a> {
+    inner: Box<dyn RowWriter + 'a>,
+    projection: Projection,
+}
+
+impl ColumnSelection {
+    pub fn is_empty(&self) -> bool {
+        self.select.is_empty()
+            && self.exclude.is_empty()
+            && self.rename.is_empty()
+            && self.order.is_empty()
+    }
+
+    pub fn resolve(&self, headers: Option<&StringRecord>) -> Result<Projection> {
+        let resolve_all = |cols: &[String]| -> Result<Vec<String>> {
+            cols.iter().map(|c| resolve_column(c, headers)).collect()
+        };
+        let rename = self
+            .rename
+            .iter()
+            .map(|(old, new)| Ok((resolve_column(old, headers)?, new.clone())))
+            .collect::<Result<_>>()?;
+        Ok(Projection {
+            select: if self.select.is_empty() {
+                None
+            } else {
+                Some(resolve_all(&self.select)?)
+            },
+            exclude: resolve_all(&self.exclude)?.into_iter().collect(),
+            order: resolve_all(&self.order)?,
+            rename,
+        })
+    }
+}
+
+impl Projection {
+    pub fn apply(&self, row: Value) -> Value {
+        let Value::Object(mut map) = row else {
+            return row;
+        };
+        if let Some(select) = &self.select {
+            map = select
+                .iter()
+                .filter_map(|k| map.remove(k).map(|v| (k.clone(), v)))
+                .collect();
+        }
+        map.retain(|k, _| !self.exclude.contains(k));
+        if !self.order.is_empty() {
+            let mut ordered: Map<_, _> = self
+                .order
+                .iter()
+                .filter_map(|k| map.remove(k).map(|v| (k.clone(), v)))
+                .collect();
+            ordered.extend(map);
+            map = ordered;
+        }
+        if !self.rename.is_empty() {
+            map = map
+                .into_iter()
+                .map(|(k, v)| match self.rename.get(&k) {
+                    Some(new) => (new.clone(), v),
+                    None => (k, v),
+                })
+                .collect();
+        }
+        Value::Object(map)
+    }
+}
+
+impl<'a> ProjectWriter<'a> {
+    pub fn new(inner: Box<dyn RowWriter + 'a>, projection: Projection) -> Self {
+        Self { inner, projection }
+    }
+}
+
+impl RowWriter for ProjectWriter<'_> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        self.inner.write_row(&self.projection.apply(row.clone()))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+
+    fn done(&self) -> bool {
+        self.inner.done()
+    }
+}
+
+// an exact header name wins over a 1-based index, so a header literally named "2" still works
+pub(crate) fn resolve_column(column: &str, headers: Option<&StringRecord>) -> Result<String> {
+    let Some(headers) = headers else {
+        // headerless input is keyed col_1..col_n
+        return Ok(match column.parse::<usize>() {
+            Ok(i) if i > 0 => format!("col_{}", i),
+            _ => column.to_string(),
+        });
+    };
+    if headers.iter().any(|h| h == column) {
+        return Ok(column.to_string());
+    }
+    match column.parse::<usize>() {
+        Ok(i) if i > 0 && i <= headers.len() => Ok(headers[i - 1].to_string()),
+        _ => Err(anyhow!("Unknown column: {}", column)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn keys(row: &Value) -> Vec<&str> {
+        row.as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_projection_select_and_rename() -> Result<()> {
+        let headers = StringRecord::from(vec!["Name", "Position", "Age"]);
+        let selection = ColumnSelection {
+            select: vec!["3".to_string(), "Name".to_string()],
+            rename: vec![("Age".to_string(), "age".to_string())],
+            ..Default::default()
+        };
+        let projection = selection.resolve(Some(&headers))?;
+        let row = projection.apply(json!({"Name": "Buffon", "Position": "Goalkeeper", "Age": 41}));
+        assert_eq!(keys(&row), vec!["age", "Name"]);
+        assert_eq!(row, json!({"age": 41, "Name": "Buffon"}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_projection_exclude_and_order() -> Result<()> {
+        let headers = StringRecord::from(vec!["a", "b", "c", "d"]);
+        let selection = ColumnSelection {
+            exclude: vec!["b".to_string()],
+            order: vec!["d".to_string(), "3".to_string()],
+            ..Default::default()
+        };
+        let projection = selection.resolve(Some(&headers))?;
+        let row = projection.apply(json!({"a": 1, "b": 2, "c": 3, "d": 4}));
+        assert_eq!(keys(&row), vec!["d", "c", "a"]);
+
+        let selection = ColumnSelection {
+            select: vec!["e".to_string()],
+            ..Default::default()
+        };
+        assert!(selection.resolve(Some(&headers)).is_err());
+        Ok(())
+    }
+}
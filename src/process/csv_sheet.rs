@@ -0,0 +1,128 @@
+use anyhow::{anyhow, bail, Result};
+use calamine::{open_workbook_auto_from_rs, Data, Reader};
+use csv::WriterBuilder;
+use std::{
+    io::{BufRead, BufReader, Cursor, Read},
+    path::Path,
+};
+
+const WORKBOOK_EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+// xlsx and ods are zip archives, xls is an OLE compound file
+const WORKBOOK_MAGIC: [&[u8]; 2] = [b"PK\x03\x04", b"\xd0\xcf\x11\xe0"];
+
+/// If `reader` holds a spreadsheet, by the extension of `input` or its first bytes, return the
+/// selected sheet as CSV; otherwise return the input unchanged.
+pub fn sheet_reader(
+    input: &str,
+    reader: Box<dyn Read>,
+    sheet: Option<&str>,
+    delimiter: u8,
+) -> Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(reader);
+    let by_extension = Path::new(input)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| WORKBOOK_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+    let head = reader.fill_buf()?;
+    if !by_extension && !WORKBOOK_MAGIC.iter().any(|magic| head.starts_with(magic)) {
+        if sheet.is_some() {
+            bail!("--sheet only applies to .xlsx, .xls or .ods input");
+        }
+        return Ok(Box::new(reader));
+    }
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(Box::new(Cursor::new(sheet_to_csv(data, sheet, delimiter)?)))
+}
+
+// `sheet` is a name or a 1-based index, the first sheet by default
+fn sheet_to_csv(data: Vec<u8>, sheet: Option<&str>, delimiter: u8) -> Result<Vec<u8>> {
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(data))
+        .map_err(|e| anyhow!("Cannot read workbook: {}", e))?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        None => names.first(),
+        Some(sheet) => names.iter().find(|name| *name == sheet).or_else(|| {
+            let i = sheet.parse::<usize>().ok()?.checked_sub(1)?;
+            names.get(i)
+        }),
+    }
+    .cloned();
+    let Some(name) = name else {
+        bail!(
+            "Sheet {} not found, the workbook has: {}",
+            sheet.unwrap_or("1"),
+            names.join(", ")
+        );
+    };
+    let range = workbook.worksheet_range(&name)?;
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_writer(Vec::new());
+    for row in range.rows() {
+        writer.write_record(row.iter().map(cell_text))?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+// dates become ISO 8601 text; floats print without a trailing `.0`, so whole numbers stay ints
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(dt) if dt.is_datetime() => match dt.as_datetime() {
+            Some(dt) if dt.time() == chrono::NaiveTime::MIN => dt.format("%Y-%m-%d").to_string(),
+            Some(dt) => dt.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            None => cell.to_string(),
+        },
+        cell => cell.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_reader;
+    use calamine::{ExcelDateTime, ExcelDateTimeType};
+
+    fn read_sheet(input: &str, reader: Box<dyn Read>, sheet: Option<&str>) -> Result<String> {
+        let mut data = String::new();
+        sheet_reader(input, reader, sheet, b',')?.read_to_string(&mut data)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn test_sheet_reader() -> Result<()> {
+        let players = "Name,Kit Number,Note\nBuffon,77,\"a, b\"\nDybala,10.5,\n";
+        for path in ["fixtures/players.xlsx", "fixtures/players.ods"] {
+            assert_eq!(read_sheet(path, get_reader(path)?, None)?, players);
+            // detected by content without the extension
+            let data = std::fs::read(path)?;
+            let teams = read_sheet("-", Box::new(Cursor::new(data)), Some("2"))?;
+            assert_eq!(teams, "team,city\na,Turin\n");
+            let err = read_sheet(path, get_reader(path)?, Some("Coaches")).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Sheet Coaches not found, the workbook has: Players, Teams"
+            );
+        }
+
+        let csv = "a,b\n1,2\n";
+        assert_eq!(read_sheet("a.csv", Box::new(csv.as_bytes()), None)?, csv);
+
+        assert!(read_sheet("a.csv", Box::new(csv.as_bytes()), Some("1")).is_err());
+        let err = read_sheet("a.xlsx", Box::new(csv.as_bytes()), None).unwrap_err();
+        assert!(err.to_string().starts_with("Cannot read workbook"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cell_text() {
+        let date = ExcelDateTime::new(45292.0, ExcelDateTimeType::DateTime, false);
+        let time = ExcelDateTime::new(45292.5, ExcelDateTimeType::DateTime, false);
+        assert_eq!(cell_text(&Data::DateTime(date)), "2024-01-01");
+        assert_eq!(cell_text(&Data::DateTime(time)), "2024-01-01T12:00:00");
+        assert_eq!(cell_text(&Data::Float(3.0)), "3");
+        assert_eq!(cell_text(&Data::Empty), "");
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use serde_json::Value;
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Seek, SeekFrom},
+    str::FromStr,
+};
+
+use super::{csv_filter::as_number, csv_select::resolve_column};
+use crate::RowWriter;
+
+// rows sorted in memory before a sorted run is spilled to a temp file
+const SORT_CHUNK_ROWS: usize = 100_000;
+// runs merged at once, so the open temp files stay well below the descriptor limit
+const MERGE_FAN_IN: usize = 64;
+
+/// A `--sort-by` key, `column[:asc|desc]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub desc: bool,
+}
+
+impl SortKey {
+    pub(crate) fn resolve(keys: &[SortKey], headers: Option<&StringRecord>) -> Result<Vec<Self>> {
+        keys.iter()
+            .map(|k| {
+                Ok(SortKey {
+                    column: resolve_column(&k.column, headers)?,
+                    desc: k.desc,
+                })
+            })
+            .collect()
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // only a known suffix is split off, so column names may contain colons
+        let (column, desc) = match s.rsplit_once(':') {
+            Some((column, "asc")) => (column, false),
+            Some((column, "desc")) => (column, true),
+            _ => (s, false),
+        };
+        if column.is_empty() {
+            return Err(anyhow!("Expected column[:asc|desc]"));
+        }
+        Ok(SortKey {
+            column: column.to_string(),
+            desc,
+        })
+    }
+}
+
+/// Compare two rows by `keys`, falling back to the next key on ties.
+pub fn compare_rows(a: &Value, b: &Value, keys: &[SortKey]) -> Ordering {
+    for key in keys {
+        let a = a.get(&key.column).unwrap_or(&Value::Null);
+        let b = b.get(&key.column).unwrap_or(&Value::Null);
+        let ordering = compare_values(a, b);
+        if ordering != Ordering::Equal {
+            return if key.desc {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+        }
+    }
+    Ordering::Equal
+}
+
+// numbers (and numeric strings of uninferred columns) sort first, then booleans, strings
+// and nulls last
+pub(crate) fn compare_values(a: &Value, b: &Value) -> Ordering {
+    if let (Some(x), Some(y)) = (as_number(a), as_number(b)) {
+        return x.total_cmp(&y);
+    }
+    match (a, b) {
+        _ if rank(a) != rank(b) => rank(a).cmp(&rank(b)),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    }
+}
+
+fn rank(v: &Value) -> u8 {
+    match v {
+        Value::Number(_) => 0,
+        Value::String(_) if as_number(v).is_some() => 0,
+        Value::Bool(_) => 1,
+        Value::String(_) => 2,
+        Value::Array(_) | Value::Object(_) => 3,
+        Value::Null => 4,
+    }
+}
+
+/// Sorts rows before passing them on, spilling sorted runs to disk for large inputs.
+pub struct SortWriter<'a> {
+    inner: Box<dyn RowWriter + 'a>,
+    keys: Vec<SortKey>,
+    rows: Vec<Value>,
+    chunk_rows: usize,
+    runs: Vec<File>,
+}
+
+impl<'a> SortWriter<'a> {
+    pub fn new(inner: Box<dyn RowWriter + 'a>, keys: Vec<SortKey>) -> Self {
+        Self::with_chunk_rows(inner, keys, SORT_CHUNK_ROWS)
+    }
+
+    fn with_chunk_rows(inner: Box<dyn RowWriter + 'a>, keys: Vec<SortKey>, n: usize) -> Self {
+        Self {
+            inner,
+            keys,
+            rows: Vec::new(),
+            chunk_rows: n,
+            runs: Vec::new(),
+        }
+    }
+
+    fn sort_chunk(&mut self) {
+        // stable, so rows with equal keys keep their input order
+        let keys = &self.keys;
+        self.rows.sort_by(|a, b| compare_rows(a, b, keys));
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        self.sort_chunk();
+        let mut file = BufWriter::new(tempfile::tempfile()?);
+        for row in self.rows.drain(..) {
+            write_run_row(&mut file, &row)?;
+        }
+        self.runs.push(end_run(file)?);
+        Ok(())
+    }
+
+    fn merge(&mut self) -> Result<()> {
+        let mut runs = std::mem::take(&mut self.runs);
+        // merge groups of neighbouring runs into longer ones until a single pass is left,
+        // which keeps rows with equal keys in input order
+        while runs.len() > MERGE_FAN_IN {
+            let mut rest = runs.into_iter();
+            runs = Vec::new();
+            loop {
+                let group: Vec<File> = rest.by_ref().take(MERGE_FAN_IN).collect();
+                if group.is_empty() {
+                    break;
+                }
+                let mut file = BufWriter::new(tempfile::tempfile()?);
+                merge_runs(group, &self.keys, |row| write_run_row(&mut file, row))?;
+                runs.push(end_run(file)?);
+            }
+        }
+        merge_runs(runs, &self.keys, |row| self.inner.write_row(row))
+    }
+}
+
+impl RowWriter for SortWriter<'_> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        self.rows.push(row.clone());
+        if self.rows.len() >= self.chunk_rows {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.runs.is_empty() {
+            self.sort_chunk();
+            for row in &self.rows {
+                self.inner.write_row(row)?;
+            }
+        } else {
+            if !self.rows.is_empty() {
+                self.spill()?;
+            }
+            self.merge()?;
+        }
+        self.inner.finish()
+    }
+}
+
+// Pass on the rows of sorted runs in order, earlier runs first on ties.
+fn merge_runs(
+    runs: Vec<File>,
+    keys: &[SortKey],
+    mut write: impl FnMut(&Value) -> Result<()>,
+) -> Result<()> {
+    let mut runs: Vec<BufReader<File>> = runs.into_iter().map(BufReader::new).collect();
+    let mut heap = BinaryHeap::new();
+    for (run, reader) in runs.iter_mut().enumerate() {
+        if let Some(row) = next_row(reader)? {
+            heap.push(MergeItem { row, run, keys });
+        }
+    }
+    while let Some(item) = heap.pop() {
+        write(&item.row)?;
+        if let Some(row) = next_row(&mut runs[item.run])? {
+            heap.push(MergeItem { row, ..item });
+        }
+    }
+    Ok(())
+}
+
+fn write_run_row(file: &mut BufWriter<File>, row: &Value) -> Result<()> {
+    // msgpack keeps floats exact, unlike a JSON round trip
+    Ok(rmp_serde::encode::write_named(file, row)?)
+}
+
+// rewind a written run for reading
+fn end_run(file: BufWriter<File>) -> Result<File> {
+    let mut file = file.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+fn next_row(reader: &mut BufReader<File>) -> Result<Option<Value>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(rmp_serde::from_read(reader)?))
+}
+
+struct MergeItem<'k> {
+    row: Value,
+    run: usize,
+    keys: &'k [SortKey],
+}
+
+impl Ord for MergeItem<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap pops the largest item, so the order is reversed; earlier runs win ties
+        compare_rows(&other.row, &self.row, self.keys).then(other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for MergeItem<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeItem<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeItem<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sort_key_from_str() -> Result<()> {
+        assert_eq!(
+            "Age:desc".parse::<SortKey>()?,
+            SortKey {
+                column: "Age".to_string(),
+                desc: true
+            }
+        );
+        assert_eq!("a:b".parse::<SortKey>()?.column, "a:b");
+        assert!(":desc".parse::<SortKey>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_writer_spills_and_merges() -> Result<()> {
+        let keys = vec!["n".parse()?, "id:desc".parse()?];
+        let mut rows = Vec::new();
+        let mut writer = SortWriter::with_chunk_rows(Box::new(&mut rows), keys, 3);
+        let values = [
+            json!("10"),
+            json!(9),
+            json!(null),
+            json!(2.5),
+            json!(10),
+            json!("x"),
+            json!("1"),
+        ];
+        for (id, n) in values.into_iter().enumerate() {
+            writer.write_row(&json!({"id": id, "n": n}))?;
+        }
+        writer.finish()?;
+        drop(writer);
+        let ids: Vec<_> = rows.iter().map(|r| r["id"].clone()).collect();
+        // numeric strings sort with the numbers, then plain strings, then nulls
+        assert_eq!(ids, vec![6, 3, 1, 4, 0, 5, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_writer_merges_in_passes() -> Result<()> {
+        let mut rows = Vec::new();
+        let mut writer = SortWriter::with_chunk_rows(Box::new(&mut rows), vec!["n".parse()?], 2);
+        let count = MERGE_FAN_IN * 5;
+        for id in 0..count {
+            writer.write_row(&json!({"id": id, "n": id % 7}))?;
+        }
+        assert!(writer.runs.len() > MERGE_FAN_IN);
+        writer.finish()?;
+        drop(writer);
+        let ids: Vec<_> = rows.iter().map(|r| r["id"].as_u64().unwrap()).collect();
+        let mut expected: Vec<_> = (0..count as u64).collect();
+        // stable: equal keys keep their input order across passes
+        expected.sort_by_key(|id| id % 7);
+        assert_eq!(ids, expected);
+        Ok(())
+    }
+}
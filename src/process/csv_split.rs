@@ -0,0 +1,333 @@
+use anyhow::{anyhow, bail, Result};
+use csv::{StringRecord, Writer, WriterBuilder};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::{csv_convert::read_headers, csv_select::resolve_column};
+use crate::{BadRowPolicy, CsvReaderOpts, RecordReader};
+
+// files kept open when splitting by value; past this all are closed and reopened on demand
+const MAX_OPEN_FILES: usize = 256;
+
+/// How `process_csv_split` cuts its input into files.
+#[derive(Debug, Clone)]
+pub enum SplitMode {
+    // records per file
+    Rows(usize),
+    // approximate file size, a file always holds at least one record
+    Bytes(u64),
+    // one file per distinct value of the column
+    Column(String),
+}
+
+struct Chunk {
+    path: PathBuf,
+    // closed while too many files are open
+    writer: Option<Writer<File>>,
+    rows: usize,
+    bytes: u64,
+}
+
+/// Split `input` into `<prefix>_<n>.csv` files, or `<prefix>_<value>.csv` when splitting by
+/// column, each starting with the header. Existing files are only replaced with `force`.
+/// Returns the files written.
+pub fn process_csv_split(
+    input: &str,
+    out_dir: &Path,
+    prefix: &str,
+    mode: &SplitMode,
+    opts: &CsvReaderOpts,
+    force: bool,
+    policy: &BadRowPolicy,
+) -> Result<Vec<PathBuf>> {
+    if matches!(mode, SplitMode::Rows(0) | SplitMode::Bytes(0)) {
+        bail!("Chunk size must be greater than zero");
+    }
+    let mut reader = opts.open(input)?;
+    let headers = read_headers(&mut reader, opts)?;
+    let by_column = match mode {
+        SplitMode::Column(column) => Some(column_index(column, headers.as_ref())?),
+        _ => None,
+    };
+    fs::create_dir_all(out_dir)?;
+    let mut records = RecordReader::new(reader, opts, policy)?;
+
+    let mut paths = Vec::new();
+    let mut current: Option<Chunk> = None;
+    // splitting by value keeps a file per distinct value, the names taken in lower case for
+    // case-insensitive file systems
+    let mut by_value: HashMap<String, Chunk> = HashMap::new();
+    let mut taken = HashSet::new();
+    let mut open = 0;
+    let mut record = StringRecord::new();
+    while records.read_record(&mut record)? {
+        let size = record_size(&record);
+        let chunk = if let Some(index) = by_column {
+            let value = record.get(index).unwrap_or_default();
+            if by_value
+                .get(value)
+                .is_none_or(|chunk| chunk.writer.is_none())
+            {
+                if open >= MAX_OPEN_FILES {
+                    for chunk in by_value.values_mut() {
+                        if let Some(mut writer) = chunk.writer.take() {
+                            writer.flush()?;
+                        }
+                    }
+                    open = 0;
+                }
+                open += 1;
+            }
+            if !by_value.contains_key(value) {
+                let path = value_path(out_dir, prefix, value, &mut taken);
+                let chunk = create_chunk(&path, headers.as_ref(), opts, force)?;
+                paths.push(path);
+                by_value.insert(value.to_string(), chunk);
+            }
+            let chunk = by_value.get_mut(value).expect("chunk was just created");
+            if chunk.writer.is_none() {
+                let file = OpenOptions::new().append(true).open(&chunk.path)?;
+                chunk.writer = Some(csv_writer(opts).from_writer(file));
+            }
+            chunk
+        } else {
+            let full = current.as_ref().is_none_or(|chunk| match mode {
+                SplitMode::Rows(rows) => chunk.rows >= *rows,
+                _ => chunk.rows > 0 && chunk.bytes + size > limit_bytes(mode),
+            });
+            if full {
+                if let Some(writer) = current.take().and_then(|chunk| chunk.writer) {
+                    writer.into_inner()?;
+                }
+                let path = out_dir.join(format!("{}_{}.csv", prefix, paths.len() + 1));
+                current = Some(create_chunk(&path, headers.as_ref(), opts, force)?);
+                paths.push(path);
+            }
+            current.as_mut().expect("chunk was just created")
+        };
+        let writer = chunk.writer.as_mut().expect("chunk is open");
+        writer.write_record(&record)?;
+        chunk.rows += 1;
+        chunk.bytes += size;
+    }
+    for chunk in current.iter_mut().chain(by_value.values_mut()) {
+        if let Some(writer) = chunk.writer.as_mut() {
+            writer.flush()?;
+        }
+    }
+    records.finish()?;
+    Ok(paths)
+}
+
+fn limit_bytes(mode: &SplitMode) -> u64 {
+    match mode {
+        SplitMode::Bytes(bytes) => *bytes,
+        _ => u64::MAX,
+    }
+}
+
+fn create_chunk(
+    path: &Path,
+    headers: Option<&StringRecord>,
+    opts: &CsvReaderOpts,
+    force: bool,
+) -> Result<Chunk> {
+    let file = match force {
+        true => File::create(path),
+        false => OpenOptions::new().write(true).create_new(true).open(path),
+    };
+    let file = match file {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            bail!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            )
+        }
+        file => file?,
+    };
+    let mut writer = csv_writer(opts).from_writer(file);
+    let mut bytes = 0;
+    if let Some(headers) = headers {
+        writer.write_record(headers)?;
+        bytes = record_size(headers);
+    }
+    Ok(Chunk {
+        path: path.to_path_buf(),
+        writer: Some(writer),
+        rows: 0,
+        bytes,
+    })
+}
+
+fn csv_writer(opts: &CsvReaderOpts) -> WriterBuilder {
+    let mut builder = WriterBuilder::new();
+    builder.delimiter(opts.delimiter);
+    builder
+}
+
+// distinct values can clean up to the same name, later ones get a numeric suffix
+fn value_path(out_dir: &Path, prefix: &str, value: &str, taken: &mut HashSet<String>) -> PathBuf {
+    let key = file_key(value);
+    let mut name = format!("{}_{}.csv", prefix, key);
+    let mut n = 1;
+    while !taken.insert(name.to_lowercase()) {
+        n += 1;
+        name = format!("{}_{}_{}.csv", prefix, key, n);
+    }
+    out_dir.join(name)
+}
+
+fn column_index(column: &str, headers: Option<&StringRecord>) -> Result<usize> {
+    let name = resolve_column(column, headers)?;
+    let index = match headers {
+        Some(headers) => headers.iter().position(|h| h == name),
+        None => name
+            .strip_prefix("col_")
+            .and_then(|i| i.parse::<usize>().ok())
+            .map(|i| i - 1),
+    };
+    index.ok_or_else(|| anyhow!("Unknown column: {}", column))
+}
+
+// written size without quoting: fields, delimiters and the line break
+fn record_size(record: &StringRecord) -> u64 {
+    (record.as_slice().len() + record.len().max(1)) as u64
+}
+
+// keep file names portable whatever the column holds
+fn file_key(value: &str) -> String {
+    let key: String = value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if key.is_empty() || key.starts_with('.') {
+        format!("_{}", key)
+    } else {
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_split() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        fs::write(&input, "id,team\n1,a\n2,b/c\n3,a\n4,b_c\n")?;
+        let input = input.to_str().unwrap();
+        let opts = CsvReaderOpts::default();
+        let policy = BadRowPolicy::Fail;
+
+        let rows = SplitMode::Rows(2);
+        let paths = process_csv_split(input, dir.path(), "rows", &rows, &opts, false, &policy)?;
+        assert_eq!(paths.len(), 2);
+        assert_eq!(read(&paths[0]), "id,team\n1,a\n2,b/c\n");
+        assert_eq!(read(&paths[1]), "id,team\n3,a\n4,b_c\n");
+
+        // the header alone is 8 bytes and each record 4 or 6
+        let bytes = SplitMode::Bytes(14);
+        let paths = process_csv_split(input, dir.path(), "bytes", &bytes, &opts, false, &policy)?;
+        assert_eq!(paths.len(), 4);
+
+        let err =
+            process_csv_split(input, dir.path(), "rows", &rows, &opts, false, &policy).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("already exists, use --force to overwrite it"));
+        process_csv_split(input, dir.path(), "rows", &rows, &opts, true, &policy)?;
+
+        let mode = SplitMode::Column("team".to_string());
+        let paths = process_csv_split(input, dir.path(), "team", &mode, &opts, false, &policy)?;
+        assert_eq!(
+            paths,
+            vec![
+                dir.path().join("team_a.csv"),
+                dir.path().join("team_b_c.csv"),
+                dir.path().join("team_b_c_2.csv")
+            ]
+        );
+        assert_eq!(read(&paths[0]), "id,team\n1,a\n3,a\n");
+        assert_eq!(read(&paths[2]), "id,team\n4,b_c\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_bad_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        fs::write(&input, "id,team\n1,a\n2\n3,b\n")?;
+        let input = input.to_str().unwrap();
+        let opts = CsvReaderOpts::default();
+        let rows = SplitMode::Rows(5);
+
+        let err = process_csv_split(
+            input,
+            dir.path(),
+            "rows",
+            &rows,
+            &opts,
+            false,
+            &BadRowPolicy::Fail,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("line 3, byte 12: expected 2 fields"));
+
+        let paths = process_csv_split(
+            input,
+            dir.path(),
+            "rows",
+            &rows,
+            &opts,
+            true,
+            &BadRowPolicy::Skip,
+        )?;
+        assert_eq!(read(&paths[0]), "id,team\n1,a\n3,b\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_many_values() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        let values = MAX_OPEN_FILES + 10;
+        let data: String = (0..values * 2)
+            .map(|i| format!("{}\n", i % values))
+            .collect();
+        fs::write(&input, format!("id\n{}", data))?;
+        let mode = SplitMode::Column("id".to_string());
+        let out_dir = dir.path().join("out");
+        let opts = CsvReaderOpts::default();
+        let policy = BadRowPolicy::Fail;
+        let paths = process_csv_split(
+            input.to_str().unwrap(),
+            &out_dir,
+            "id",
+            &mode,
+            &opts,
+            false,
+            &policy,
+        )?;
+        assert_eq!(paths.len(), values);
+        // closed files are reopened for appending, without a second header
+        assert_eq!(read(&paths[0]), "id\n0\n0\n");
+        Ok(())
+    }
+}
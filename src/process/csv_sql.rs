@@ -0,0 +1,487 @@
+use anyhow::{bail, Result};
+use rusqlite::{params_from_iter, types::Value as SqliteValue, Connection};
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Write},
+};
+use tempfile::NamedTempFile;
+
+use crate::{cli::SqlDialect, BatchSchema, RowWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SqlType {
+    Integer,
+    Float,
+    Boolean,
+    Text,
+    Json,
+}
+
+// column names and types, decided by every row when they are all in memory and by the first
+// batch when streaming
+struct Table {
+    name: String,
+    columns: Vec<(String, SqlType)>,
+}
+
+// the rows of the next insert and the table they go to, shared by both SQL writers
+struct Batch {
+    name: String,
+    size: usize,
+    table: Option<Table>,
+    created: bool,
+    rows: Vec<Value>,
+}
+
+/// Writes `CREATE TABLE` and one multi-row `INSERT` per batch of rows.
+pub struct SqlRowWriter<W: Write> {
+    writer: W,
+    dialect: SqlDialect,
+    batch: Batch,
+}
+
+/// Builds a SQLite database in a temporary file and copies it to the output when done.
+pub struct SqliteRowWriter<W: Write> {
+    writer: W,
+    db: Option<(NamedTempFile, Connection)>,
+    batch: Batch,
+}
+
+impl Batch {
+    fn new(name: &str, size: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            size: size.max(1),
+            table: None,
+            created: false,
+            rows: Vec::new(),
+        }
+    }
+
+    // buffer `row`, true once the batch is full
+    fn push(&mut self, row: &Value) -> bool {
+        self.rows.push(row.clone());
+        self.rows.len() >= self.size
+    }
+
+    // pass the buffered rows to `write` with their table, decided by the first batch unless
+    // it was given, and whether the table still has to be created
+    fn flush(
+        &mut self,
+        dialect: SqlDialect,
+        write: impl FnOnce(&Table, bool, &[Value]) -> Result<()>,
+    ) -> Result<()> {
+        let table = self
+            .table
+            .get_or_insert_with(|| Table::infer(&self.name, &self.rows));
+        table.check(&self.rows, self.size, dialect)?;
+        write(table, !self.created, &self.rows)?;
+        self.created = true;
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> SqlRowWriter<W> {
+    pub fn new(writer: W, dialect: SqlDialect, table: &str, batch_size: usize) -> Self {
+        Self {
+            writer,
+            dialect,
+            batch: Batch::new(table, batch_size),
+        }
+    }
+
+    fn flush_batch(&mut self) -> Result<()> {
+        let (writer, dialect) = (&mut self.writer, self.dialect);
+        self.batch.flush(dialect, |table, create, rows| {
+            if table.columns.is_empty() {
+                return Ok(());
+            }
+            if create {
+                writer.write_all(table.create_sql(dialect).as_bytes())?;
+            }
+            if !rows.is_empty() {
+                writer.write_all(table.insert_sql(rows, dialect).as_bytes())?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> BatchSchema for SqlRowWriter<W> {
+    fn with_rows(mut self, rows: &[Value]) -> Self {
+        self.batch.table = Some(Table::infer(&self.batch.name, rows));
+        self
+    }
+}
+
+impl<W: Write> RowWriter for SqlRowWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        if self.batch.push(row) {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush_batch()?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<W: Write> SqliteRowWriter<W> {
+    pub fn new(writer: W, table: &str, batch_size: usize) -> Self {
+        Self {
+            writer,
+            db: None,
+            batch: Batch::new(table, batch_size),
+        }
+    }
+
+    fn open(&mut self) -> Result<()> {
+        if self.db.is_none() {
+            let file = NamedTempFile::new()?;
+            let conn = Connection::open(file.path())?;
+            // one transaction for the whole load, committed in `finish`
+            conn.execute_batch("BEGIN")?;
+            self.db = Some((file, conn));
+        }
+        Ok(())
+    }
+
+    fn flush_batch(&mut self) -> Result<()> {
+        self.open()?;
+        let (_, conn) = self.db.as_ref().expect("database was just opened");
+        self.batch.flush(SqlDialect::Sqlite, |table, create, rows| {
+            if create {
+                create_table(conn, table)?;
+            }
+            insert_rows(conn, table, rows)
+        })
+    }
+}
+
+impl<W: Write> BatchSchema for SqliteRowWriter<W> {
+    fn with_rows(mut self, rows: &[Value]) -> Self {
+        self.batch.table = Some(Table::infer(&self.batch.name, rows));
+        self
+    }
+}
+
+impl<W: Write> RowWriter for SqliteRowWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        if self.batch.push(row) {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush_batch()?;
+        let (file, conn) = self.db.take().expect("database was just opened");
+        conn.execute_batch("COMMIT")?;
+        conn.close().map_err(|(_, e)| e)?;
+        io::copy(&mut File::open(file.path())?, &mut self.writer)?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Create the table `name` in `conn` and insert `rows`; `headers` adds the columns that are
+/// empty in every row.
+pub(crate) fn load_table(
+    conn: &Connection,
+    name: &str,
+    headers: &[String],
+    rows: &[Value],
+) -> Result<()> {
+    let mut table = Table::infer(name, rows);
+    for header in headers {
+        if !table.columns.iter().any(|(column, _)| column == header) {
+            table.columns.push((header.clone(), SqlType::Text));
+        }
+    }
+    create_table(conn, &table)?;
+    insert_rows(conn, &table, rows)
+}
+
+fn create_table(conn: &Connection, table: &Table) -> Result<()> {
+    if !table.columns.is_empty() {
+        conn.execute_batch(&table.create_sql(SqlDialect::Sqlite))?;
+    }
+    Ok(())
+}
+
+fn insert_rows(conn: &Connection, table: &Table, rows: &[Value]) -> Result<()> {
+    if rows.is_empty() || table.columns.is_empty() {
+        return Ok(());
+    }
+    let placeholders = vec!["?"; table.columns.len()].join(", ");
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        quote_ident(&table.name, SqlDialect::Sqlite),
+        table.column_list(SqlDialect::Sqlite),
+        placeholders
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    for row in rows {
+        let values = table
+            .columns
+            .iter()
+            .map(|(name, _)| sqlite_value(&row[name.as_str()]));
+        stmt.execute(params_from_iter(values))?;
+    }
+    Ok(())
+}
+
+impl Table {
+    fn infer(name: &str, rows: &[Value]) -> Self {
+        let mut seen = HashSet::new();
+        let mut columns: Vec<(String, Option<SqlType>)> = Vec::new();
+        for (key, value) in rows.iter().filter_map(Value::as_object).flatten() {
+            if seen.insert(key.as_str()) {
+                columns.push((key.clone(), None));
+            }
+            let Some(ty) = value_type(value) else {
+                continue;
+            };
+            let column = columns
+                .iter_mut()
+                .find(|(k, _)| k == key)
+                .expect("column seen");
+            column.1 = Some(match column.1.take() {
+                None => ty,
+                Some(prev) => merge_types(prev, ty),
+            });
+        }
+        Self {
+            name: name.to_string(),
+            columns: columns
+                .into_iter()
+                .map(|(name, ty)| (name, ty.unwrap_or(SqlType::Text)))
+                .collect(),
+        }
+    }
+
+    // streamed rows after the first batch must fit the table it decided, rather than lose data
+    fn check(&self, rows: &[Value], batch_size: usize, dialect: SqlDialect) -> Result<()> {
+        for (key, value) in rows.iter().filter_map(Value::as_object).flatten() {
+            let Some((_, column)) = self.columns.iter().find(|(name, _)| name == key) else {
+                bail!(
+                    "Column {} is not in the first {} rows that decided the table, \
+                     use a larger --batch-size",
+                    key,
+                    batch_size
+                );
+            };
+            if let Some(ty) = value_type(value) {
+                if merge_types(*column, ty) != *column {
+                    bail!(
+                        "Column {} is {} from the first {} rows but has the value {}, \
+                         use a larger --batch-size or --infer column",
+                        key,
+                        type_name(*column, dialect),
+                        batch_size,
+                        value
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn column_list(&self, dialect: SqlDialect) -> String {
+        self.columns
+            .iter()
+            .map(|(name, _)| quote_ident(name, dialect))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn create_sql(&self, dialect: SqlDialect) -> String {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|(name, ty)| {
+                format!(
+                    "  {} {}",
+                    quote_ident(name, dialect),
+                    type_name(*ty, dialect)
+                )
+            })
+            .collect();
+        format!(
+            "CREATE TABLE {} (\n{}\n);\n",
+            quote_ident(&self.name, dialect),
+            columns.join(",\n")
+        )
+    }
+
+    fn insert_sql(&self, rows: &[Value], dialect: SqlDialect) -> String {
+        let values: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let values: Vec<String> = self
+                    .columns
+                    .iter()
+                    .map(|(name, ty)| literal(&row[name.as_str()], *ty, dialect))
+                    .collect();
+                format!("  ({})", values.join(", "))
+            })
+            .collect();
+        format!(
+            "INSERT INTO {} ({}) VALUES\n{};\n",
+            quote_ident(&self.name, dialect),
+            self.column_list(dialect),
+            values.join(",\n")
+        )
+    }
+}
+
+fn value_type(value: &Value) -> Option<SqlType> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some(SqlType::Boolean),
+        Value::Number(n) if n.is_i64() => Some(SqlType::Integer),
+        Value::Number(_) => Some(SqlType::Float),
+        Value::String(_) => Some(SqlType::Text),
+        Value::Array(_) | Value::Object(_) => Some(SqlType::Json),
+    }
+}
+
+// integers widen to floats, any other mix to text
+fn merge_types(prev: SqlType, ty: SqlType) -> SqlType {
+    match (prev, ty) {
+        (prev, ty) if prev == ty => ty,
+        (SqlType::Integer | SqlType::Float, SqlType::Integer | SqlType::Float) => SqlType::Float,
+        _ => SqlType::Text,
+    }
+}
+
+fn type_name(ty: SqlType, dialect: SqlDialect) -> &'static str {
+    match (ty, dialect) {
+        (SqlType::Integer, SqlDialect::Sqlite) => "INTEGER",
+        (SqlType::Integer, _) => "BIGINT",
+        (SqlType::Float, SqlDialect::Postgres) => "DOUBLE PRECISION",
+        (SqlType::Float, SqlDialect::Mysql) => "DOUBLE",
+        (SqlType::Float, SqlDialect::Sqlite) => "REAL",
+        // SQLite has no boolean type, true and false are 1 and 0
+        (SqlType::Boolean, SqlDialect::Sqlite) => "INTEGER",
+        (SqlType::Boolean, _) => "BOOLEAN",
+        (SqlType::Json, SqlDialect::Postgres) => "JSONB",
+        (SqlType::Json, SqlDialect::Mysql) => "JSON",
+        (SqlType::Json, SqlDialect::Sqlite) | (SqlType::Text, _) => "TEXT",
+    }
+}
+
+fn quote_ident(name: &str, dialect: SqlDialect) -> String {
+    match dialect {
+        SqlDialect::Mysql => format!("`{}`", name.replace('`', "``")),
+        _ => format!("\"{}\"", name.replace('"', "\"\"")),
+    }
+}
+
+// values of text columns are always quoted, the column may mix numbers and strings
+fn literal(value: &Value, ty: SqlType, dialect: SqlDialect) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::String(s) => quote_string(s, dialect),
+        v if ty == SqlType::Text => quote_string(&v.to_string(), dialect),
+        Value::Bool(b) if dialect == SqlDialect::Sqlite => (*b as u8).to_string(),
+        Value::Bool(b) => b.to_string().to_uppercase(),
+        Value::Number(n) => n.to_string(),
+        v => quote_string(&v.to_string(), dialect),
+    }
+}
+
+fn quote_string(s: &str, dialect: SqlDialect) -> String {
+    let s = s.replace('\'', "''");
+    match dialect {
+        // MySQL also treats backslashes in string literals as escapes
+        SqlDialect::Mysql => format!("'{}'", s.replace('\\', "\\\\")),
+        _ => format!("'{}'", s),
+    }
+}
+
+fn sqlite_value(value: &Value) -> SqliteValue {
+    match value {
+        Value::Null => SqliteValue::Null,
+        Value::Bool(b) => SqliteValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqliteValue::Integer(i),
+            None => SqliteValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => SqliteValue::Text(s.clone()),
+        v => SqliteValue::Text(v.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sql_row_writer() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut writer = SqlRowWriter::new(&mut buf, SqlDialect::Mysql, "players", 2);
+        writer.write_row(&json!({"name": "O'Neil", "age": 30, "active": true}))?;
+        writer.write_row(&json!({"name": "a\\b", "age": 1.5, "active": null}))?;
+        writer.write_row(&json!({"name": "c", "age": 2, "active": false}))?;
+        writer.finish()?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "CREATE TABLE `players` (\n  `name` TEXT,\n  `age` DOUBLE,\n  `active` BOOLEAN\n);\n\
+             INSERT INTO `players` (`name`, `age`, `active`) VALUES\n  ('O''Neil', 30, TRUE),\n  ('a\\\\b', 1.5, NULL);\n\
+             INSERT INTO `players` (`name`, `age`, `active`) VALUES\n  ('c', 2, FALSE);\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sql_row_writer_late_column() -> Result<()> {
+        let rows = vec![json!({"a": "1"}), json!({"a": "2", "b": 3})];
+        let mut buf = Vec::new();
+        let mut writer = SqlRowWriter::new(&mut buf, SqlDialect::Postgres, "t", 1).with_rows(&rows);
+        for row in &rows {
+            writer.write_row(row)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "CREATE TABLE \"t\" (\n  \"a\" TEXT,\n  \"b\" BIGINT\n);\n\
+             INSERT INTO \"t\" (\"a\", \"b\") VALUES\n  ('1', NULL);\n\
+             INSERT INTO \"t\" (\"a\", \"b\") VALUES\n  ('2', 3);\n"
+        );
+
+        // streamed, the first batch decides the table and later rows must fit it
+        let mut writer = SqlRowWriter::new(io::sink(), SqlDialect::Postgres, "t", 1);
+        writer.write_row(&rows[0])?;
+        let err = writer.write_row(&rows[1]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Column b is not in the first 1 rows"));
+        let mut writer = SqlRowWriter::new(io::sink(), SqlDialect::Postgres, "t", 1);
+        writer.write_row(&json!({"a": 1}))?;
+        assert!(writer.write_row(&json!({"a": "x"})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_row_writer() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out.sqlite");
+        let mut writer = SqliteRowWriter::new(File::create(&path)?, "players", 1);
+        writer.write_row(&json!({"name": "Anna", "age": 30}))?;
+        writer.write_row(&json!({"name": "Bob", "age": null}))?;
+        writer.finish()?;
+
+        let conn = Connection::open(&path)?;
+        let total: i64 = conn.query_row("SELECT sum(age) FROM players", [], |r| r.get(0))?;
+        let count: i64 = conn.query_row("SELECT count(*) FROM players", [], |r| r.get(0))?;
+        assert_eq!((total, count), (30, 2));
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::Result;
+use csv::{Reader, StringRecord};
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::HashMap, io::Read};
+
+use super::{
+    csv_convert::read_headers,
+    csv_table::{column_widths, line_text, table_line},
+};
+use crate::{ColumnType, CsvReaderOpts};
+
+#[derive(Debug, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: ColumnType,
+    pub count: usize,
+    pub nulls: usize,
+    pub distinct: usize,
+    pub min: Value,
+    pub max: Value,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub top: Vec<TopValue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopValue {
+    pub value: String,
+    pub count: usize,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    ty: Option<ColumnType>,
+    counts: HashMap<String, usize>,
+    numbers: Vec<f64>,
+    // exact bounds of integer columns, which f64 rounds past 2^53
+    int_bounds: Option<(i64, i64)>,
+}
+
+impl Accumulator {
+    fn add(&mut self, s: &str) {
+        let ty = ColumnType::detect(s);
+        self.ty = Some(self.ty.map_or(ty, |t| t.merge(ty)));
+        if ty == ColumnType::Null {
+            return;
+        }
+        if matches!(ty, ColumnType::Integer | ColumnType::Float) {
+            if let Ok(n) = s.parse() {
+                self.numbers.push(n);
+            }
+            if let Ok(n) = s.parse::<i64>() {
+                let (min, max) = self.int_bounds.get_or_insert((n, n));
+                (*min, *max) = ((*min).min(n), (*max).max(n));
+            }
+        }
+        *self.counts.entry(s.to_string()).or_default() += 1;
+    }
+
+    fn finish(mut self, name: String, count: usize, top: usize) -> ColumnStats {
+        let ty = self.ty.unwrap_or(ColumnType::Null);
+        // rows that are too short for this column count as nulls
+        let nulls = count - self.counts.values().sum::<usize>();
+        let numeric = matches!(ty, ColumnType::Integer | ColumnType::Float);
+        let (min, max, mean, median) = if numeric && !self.numbers.is_empty() {
+            self.numbers.sort_by(f64::total_cmp);
+            let n = self.numbers.len();
+            let median = if n % 2 == 1 {
+                self.numbers[n / 2]
+            } else {
+                (self.numbers[n / 2 - 1] + self.numbers[n / 2]) / 2.0
+            };
+            let mean = self.numbers.iter().sum::<f64>() / n as f64;
+            let (min, max) = match self.int_bounds {
+                Some((min, max)) if ty == ColumnType::Integer => {
+                    (Value::from(min), Value::from(max))
+                }
+                _ => (
+                    Value::from(self.numbers[0]),
+                    Value::from(self.numbers[n - 1]),
+                ),
+            };
+            (min, max, Some(mean), Some(median))
+        } else {
+            let min = self
+                .counts
+                .keys()
+                .min()
+                .map_or(Value::Null, |s| Value::from(s.as_str()));
+            let max = self
+                .counts
+                .keys()
+                .max()
+                .map_or(Value::Null, |s| Value::from(s.as_str()));
+            (min, max, None, None)
+        };
+
+        let mut frequent: Vec<_> = self.counts.iter().collect();
+        frequent.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let top = frequent
+            .into_iter()
+            .take(top)
+            .map(|(value, count)| TopValue {
+                value: value.clone(),
+                count: *count,
+            })
+            .collect();
+
+        ColumnStats {
+            name,
+            ty,
+            count,
+            nulls,
+            distinct: self.counts.len(),
+            min,
+            max,
+            mean,
+            median,
+            top,
+        }
+    }
+}
+
+/// Profile every column of the CSV in `reader`, keeping the `top` most frequent values.
+pub fn process_csv_stats<R: Read>(
+    reader: &mut Reader<R>,
+    opts: &CsvReaderOpts,
+    top: usize,
+) -> Result<Vec<ColumnStats>> {
+    let headers = read_headers(reader, opts)?;
+    let mut columns: Vec<Accumulator> = Vec::new();
+    let mut record = StringRecord::new();
+    let mut count = 0;
+    while reader.read_record(&mut record)? {
+        if columns.len() < record.len() {
+            columns.resize_with(record.len(), Default::default);
+        }
+        for (acc, field) in columns.iter_mut().zip(record.iter()) {
+            acc.add(field);
+        }
+        count += 1;
+    }
+    let width = headers.as_ref().map_or(0, |h| h.len()).max(columns.len());
+    columns.resize_with(width, Default::default);
+
+    let stats = columns
+        .into_iter()
+        .enumerate()
+        .map(|(i, acc)| {
+            let name = match headers.as_ref().and_then(|h| h.get(i)) {
+                Some(name) => name.to_string(),
+                None => format!("col_{}", i + 1),
+            };
+            acc.finish(name, count, top)
+        })
+        .collect();
+    Ok(stats)
+}
+
+/// Render column stats as an aligned plain text table.
+pub fn render_stats_table(stats: &[ColumnStats]) -> String {
+    let headers = [
+        "column", "type", "count", "nulls", "distinct", "min", "max", "mean", "median", "top",
+    ];
+    let rows: Vec<Vec<String>> = stats
+        .iter()
+        .map(|s| {
+            let top = s
+                .top
+                .iter()
+                .map(|t| format!("{} ({})", t.value, t.count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            vec![
+                line_text(&Value::from(s.name.as_str())),
+                s.ty.to_string(),
+                s.count.to_string(),
+                s.nulls.to_string(),
+                s.distinct.to_string(),
+                line_text(&s.min),
+                line_text(&s.max),
+                s.mean.map_or_else(String::new, |n| format!("{:.2}", n)),
+                s.median.map_or_else(String::new, |n| n.to_string()),
+                line_text(&Value::from(top)),
+            ]
+        })
+        .collect();
+
+    let headers: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    let widths = column_widths(&headers, &rows);
+    let numeric = vec![false; headers.len()];
+    let line = |cells: &[String]| table_line(cells, &widths, &numeric) + "\n";
+    let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    let mut ret = line(&headers);
+    ret.push_str(&line(&rule));
+    for row in &rows {
+        ret.push_str(&line(row));
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_csv_stats() -> Result<()> {
+        let data = "name,age,team\nAnna,30,a\nBob,,b\nCarl,41,a\nDan,35,\n";
+        let opts = CsvReaderOpts::default();
+        let stats = process_csv_stats(&mut opts.from_reader(data.as_bytes()), &opts, 1)?;
+        assert_eq!(stats.len(), 3);
+
+        let age = &stats[1];
+        assert_eq!(age.ty, ColumnType::Integer);
+        assert_eq!((age.count, age.nulls, age.distinct), (4, 1, 3));
+        assert_eq!((age.min.clone(), age.max.clone()), (30.into(), 41.into()));
+        assert_eq!(age.mean.map(|m| (m * 100.0).round()), Some(3533.0));
+        assert_eq!(age.median, Some(35.0));
+
+        let team = &stats[2];
+        assert_eq!(team.ty, ColumnType::String);
+        assert_eq!(team.nulls, 1);
+        assert_eq!(team.top[0].value, "a");
+        assert_eq!(team.top[0].count, 2);
+        assert!(render_stats_table(&stats).starts_with("column  type     count  nulls"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_exact_bounds_and_wide_text() -> Result<()> {
+        let data = "id,東京東京\n9007199254740993,a\n9007199254740992,b\n";
+        let opts = CsvReaderOpts::default();
+        let stats = process_csv_stats(&mut opts.from_reader(data.as_bytes()), &opts, 0)?;
+        assert_eq!(stats[0].min, Value::from(9007199254740992_i64));
+        assert_eq!(stats[0].max, Value::from(9007199254740993_i64));
+
+        // the wide name takes eight columns, not four
+        let table = render_stats_table(&stats);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("column    type"));
+        assert!(lines[2].starts_with("id        integer"));
+        assert!(lines[3].starts_with("東京東京  string"));
+        Ok(())
+    }
+}
@@ -0,0 +1,510 @@
+use anyhow::Result;
+use csv::StringRecord;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::{Map, Value};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Seek, Write},
+};
+
+use super::{
+    csv_convert::{read_headers, record_to_value, replace_nulls},
+    csv_parquet::ParquetRowWriter,
+};
+use crate::{
+    cli::{InferMode, OutputFormat, TomlNullPolicy},
+    detect_row_types, get_writer, infer_row, BadRowPolicy, CsvConvertOpts, CsvReaderOpts,
+    CsvSchema, Dedupe, DedupeWriter, GroupBy, GroupWriter, HtmlWriter, MarkdownWriter, MaskWriter,
+    NestWriter, ProjectWriter, RecordReader, RowReader, SortKey, SortWriter, SqlRowWriter,
+    SqliteRowWriter,
+};
+
+// how many records to process between progress bar updates
+const PROGRESS_INTERVAL: usize = 4096;
+
+pub trait RowWriter {
+    // write a single converted row to the underlying output
+    fn write_row(&mut self, row: &Value) -> Result<()>;
+    // close any open structure (e.g. the JSON array) and flush
+    fn finish(&mut self) -> Result<()>;
+    // whether further rows would be ignored, so the caller can stop reading early
+    fn done(&self) -> bool {
+        false
+    }
+}
+
+/// Writers whose columns and types are decided by the first batch of rows they are given.
+pub trait BatchSchema {
+    /// Decide the columns and types from `rows` instead, when every row to be written is known
+    /// up front, so a later row cannot bring a column or a wider type the first batch lacked.
+    fn with_rows(self, rows: &[Value]) -> Self;
+}
+
+pub struct JsonArrayWriter<W: Write> {
+    writer: W,
+    count: usize,
+}
+
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+}
+
+pub struct YamlSeqWriter<W: Write> {
+    writer: W,
+    count: usize,
+}
+
+pub struct MsgpackWriter<W: Write> {
+    writer: W,
+    // rows are spooled to a temporary file until their count, needed by the array header, is known
+    spool: Option<BufWriter<File>>,
+    count: usize,
+}
+
+pub struct CborWriter<W: Write> {
+    writer: W,
+    count: usize,
+}
+
+pub struct TomlTableWriter<W: Write> {
+    writer: W,
+    root: String,
+    null: TomlNullPolicy,
+    count: usize,
+}
+
+impl<W: Write> RowWriter for JsonArrayWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        let sep = if self.count == 0 { "[\n" } else { ",\n" };
+        self.writer.write_all(sep.as_bytes())?;
+        // indent the row so the output matches serde_json's pretty printed array
+        let pretty = serde_json::to_string_pretty(row)?;
+        for (i, line) in pretty.lines().enumerate() {
+            if i > 0 {
+                self.writer.write_all(b"\n")?;
+            }
+            write!(self.writer, "  {}", line)?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let end = if self.count == 0 { "[]" } else { "\n]" };
+        self.writer.write_all(end.as_bytes())?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<W: Write> RowWriter for NdjsonWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, row)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<W: Write> RowWriter for YamlSeqWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        // a one-element sequence yields the `- ` item for this row, as in the in-memory list
+        serde_yaml::to_writer(&mut self.writer, &[row])?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            self.writer.write_all(b"[]\n")?;
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<W: Write> RowWriter for MsgpackWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        let spool = match &mut self.spool {
+            Some(spool) => spool,
+            spool => spool.insert(BufWriter::new(tempfile::tempfile()?)),
+        };
+        rmp_serde::encode::write_named(spool, row)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // one array of maps, like the in-memory conversion
+        self.writer.write_all(&msgpack_array_header(self.count)?)?;
+        if let Some(spool) = self.spool.take() {
+            let mut file = spool.into_inner()?;
+            file.rewind()?;
+            io::copy(&mut file, &mut self.writer)?;
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+// MessagePack fixarray, array 16 and array 32 headers
+fn msgpack_array_header(len: usize) -> Result<Vec<u8>> {
+    Ok(match len {
+        0..=15 => vec![0x90 | len as u8],
+        16..=0xffff => [&[0xdc][..], &(len as u16).to_be_bytes()].concat(),
+        _ => [&[0xdd][..], &u32::try_from(len)?.to_be_bytes()].concat(),
+    })
+}
+
+// CBOR indefinite-length array markers, so rows can be written before the count is known
+const CBOR_ARRAY_START: u8 = 0x9f;
+const CBOR_BREAK: u8 = 0xff;
+
+impl<W: Write> RowWriter for CborWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        if self.count == 0 {
+            self.writer.write_all(&[CBOR_ARRAY_START])?;
+        }
+        ciborium::into_writer(row, &mut self.writer)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            self.writer.write_all(&[CBOR_ARRAY_START])?;
+        }
+        self.writer.write_all(&[CBOR_BREAK])?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<W: Write> RowWriter for TomlTableWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        if self.count > 0 {
+            self.writer.write_all(b"\n")?;
+        }
+        // serializing a one-element array of tables yields the `[[root]]` block for this row
+        let mut root = Map::new();
+        let row = replace_nulls(row.clone(), self.null);
+        root.insert(self.root.clone(), Value::Array(vec![row]));
+        self.writer.write_all(toml::to_string(&root)?.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+// collects rows in memory, for callers that need the whole result at once
+impl RowWriter for &mut Vec<Value> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        self.push(row.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub fn row_writer<'a, W: Write + Send + 'a>(
+    format: OutputFormat,
+    writer: W,
+    opts: &CsvConvertOpts,
+) -> Box<dyn RowWriter + 'a> {
+    match format {
+        OutputFormat::Json => Box::new(JsonArrayWriter { writer, count: 0 }),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter { writer }),
+        OutputFormat::Yaml => Box::new(YamlSeqWriter { writer, count: 0 }),
+        OutputFormat::Toml => Box::new(TomlTableWriter {
+            writer,
+            root: opts.toml_root.clone(),
+            null: opts.toml_null,
+            count: 0,
+        }),
+        OutputFormat::Msgpack => Box::new(MsgpackWriter {
+            writer,
+            spool: None,
+            count: 0,
+        }),
+        OutputFormat::Cbor => Box::new(CborWriter { writer, count: 0 }),
+        OutputFormat::Parquet => Box::new(ParquetRowWriter::new(writer)),
+        OutputFormat::Sql => Box::new(SqlRowWriter::new(
+            writer,
+            opts.sql_dialect,
+            &opts.sql_table,
+            opts.sql_batch_size,
+        )),
+        OutputFormat::Sqlite => Box::new(SqliteRowWriter::new(
+            writer,
+            &opts.sql_table,
+            opts.sql_batch_size,
+        )),
+        OutputFormat::Markdown => Box::new(MarkdownWriter::new(writer)),
+        OutputFormat::Html => Box::new(HtmlWriter::new(writer, opts.html_fragment)),
+    }
+}
+
+/// Like `row_writer`, for `rows` that are all in memory: the SQL table and the parquet schema
+/// are decided by every row instead of the first batch.
+pub fn rows_writer<'a, W: Write + Send + 'a>(
+    format: OutputFormat,
+    writer: W,
+    opts: &CsvConvertOpts,
+    rows: &[Value],
+) -> Box<dyn RowWriter + 'a> {
+    match format {
+        OutputFormat::Parquet => Box::new(ParquetRowWriter::new(writer).with_rows(rows)),
+        OutputFormat::Sql => Box::new(
+            SqlRowWriter::new(
+                writer,
+                opts.sql_dialect,
+                &opts.sql_table,
+                opts.sql_batch_size,
+            )
+            .with_rows(rows),
+        ),
+        OutputFormat::Sqlite => Box::new(
+            SqliteRowWriter::new(writer, &opts.sql_table, opts.sql_batch_size).with_rows(rows),
+        ),
+        format => row_writer(format, writer, opts),
+    }
+}
+
+/// Wrap `writer` with the dedupe, group-by, sort, masking, projection and nesting steps of
+/// `opts`, in that order.
+pub(crate) fn pipeline_writer<'a>(
+    mut writer: Box<dyn RowWriter + 'a>,
+    headers: Option<&StringRecord>,
+    opts: &CsvConvertOpts,
+) -> Result<Box<dyn RowWriter + 'a>> {
+    let dedupe = (!opts.dedupe.is_empty())
+        .then(|| Dedupe::new(&opts.dedupe, headers))
+        .transpose()?;
+    let group = (!opts.group_by.is_empty())
+        .then(|| GroupBy::new(&opts.group_by, &opts.aggregates, headers))
+        .transpose()?;
+    // after grouping, sort keys and columns refer to the aggregated rows
+    let group_headers = group.as_ref().map(GroupBy::headers);
+    let headers = group_headers.as_ref().or(headers);
+
+    // built inside out, the last wrapper sees the rows first
+    if opts.nest {
+        writer = Box::new(NestWriter::new(writer));
+    }
+    if !opts.columns.is_empty() {
+        writer = Box::new(ProjectWriter::new(writer, opts.columns.resolve(headers)?));
+    }
+    if !opts.masks.is_empty() {
+        writer = Box::new(MaskWriter::new(
+            writer,
+            &opts.masks,
+            opts.mask_key.as_deref(),
+            headers,
+        )?);
+    }
+    if !opts.sort.is_empty() {
+        writer = Box::new(SortWriter::new(
+            writer,
+            SortKey::resolve(&opts.sort, headers)?,
+        ));
+    }
+    if let Some(group) = group {
+        writer = Box::new(GroupWriter::new(writer, group));
+    }
+    if let Some(dedupe) = dedupe {
+        writer = Box::new(DedupeWriter::new(writer, dedupe));
+    }
+    Ok(writer)
+}
+
+/// Convert `input` record by record, keeping only one row in memory at a time.
+pub fn stream_csv(
+    input: &str,
+    output: &str,
+    format: OutputFormat,
+    opts: &CsvReaderOpts,
+    convert_opts: &CsvConvertOpts,
+) -> Result<()> {
+    let make_writer = || {
+        let writer = BufWriter::new(get_writer(output)?);
+        Ok(row_writer(format, writer, convert_opts))
+    };
+    stream_rows(input, make_writer, opts, convert_opts, true)
+}
+
+// the writer is only created once the input has been checked, so errors leave no empty output;
+// `progress` draws progress bars on stderr
+pub(crate) fn stream_rows<'a>(
+    input: &str,
+    make_writer: impl FnOnce() -> Result<Box<dyn RowWriter + 'a>>,
+    opts: &CsvReaderOpts,
+    convert_opts: &CsvConvertOpts,
+    progress: bool,
+) -> Result<()> {
+    // column inference needs every value up front, so scan the file once before converting
+    let types = match convert_opts.infer {
+        InferMode::Column if input == "-" => {
+            anyhow::bail!("Cannot stream stdin with column inference, use --infer cell")
+        }
+        InferMode::Column => scan_column_types(input, opts, convert_opts, progress)?,
+        _ => convert_opts.schema.clone(),
+    };
+
+    let bar = progress_bar(input, "converting", progress)?;
+    let mut reader = RowReader::open(input, opts, convert_opts.threads, &convert_opts.bad_rows)?;
+    if let Some(filter) = &convert_opts.filter {
+        filter.check_columns(reader.headers())?;
+    }
+    let mut writer = pipeline_writer(make_writer()?, reader.headers(), convert_opts)?;
+    // inference runs on the parser threads
+    let infer = |row: &mut Value| infer_row(row, &types, convert_opts.infer);
+    let mut count = 0;
+    while !writer.done() {
+        let Some(row) = reader.next_row(&infer)? else {
+            break;
+        };
+        if convert_opts.filter.as_ref().is_none_or(|f| f.eval(&row)) {
+            writer.write_row(&row)?;
+        }
+        count += 1;
+        if count % PROGRESS_INTERVAL == 0 {
+            bar.set_position(reader.byte());
+        }
+    }
+    writer.finish()?;
+    reader.finish()?;
+    bar.finish_with_message(format!("converted {} records", count));
+    Ok(())
+}
+
+fn scan_column_types(
+    input: &str,
+    opts: &CsvReaderOpts,
+    convert_opts: &CsvConvertOpts,
+    progress: bool,
+) -> Result<CsvSchema> {
+    let bar = progress_bar(input, "inferring types", progress)?;
+    let mut reader = opts.open(input)?;
+    let headers = read_headers(&mut reader, opts)?;
+    // bad rows are reported and saved by the conversion pass
+    let policy = match convert_opts.bad_rows {
+        BadRowPolicy::Fail => BadRowPolicy::Fail,
+        _ => BadRowPolicy::Skip,
+    };
+    let mut records = RecordReader::new(reader, opts, &policy)?;
+    let schema = &convert_opts.schema;
+    let mut types = schema.clone();
+    let mut record = StringRecord::new();
+    let mut count = 0;
+    while records.read_record(&mut record)? {
+        let row = record_to_value(headers.as_ref(), &record);
+        detect_row_types(&row, &mut types, schema);
+        count += 1;
+        if count % PROGRESS_INTERVAL == 0 {
+            bar.set_position(records.position().byte());
+        }
+    }
+    bar.finish_and_clear();
+    Ok(types)
+}
+
+fn progress_bar(input: &str, msg: &'static str, progress: bool) -> Result<ProgressBar> {
+    // indicatif draws to stderr and stays hidden when it is not a terminal
+    if !progress {
+        return Ok(ProgressBar::hidden());
+    }
+    if input == "-" {
+        let bar = ProgressBar::new_spinner().with_message(msg);
+        bar.set_style(ProgressStyle::with_template(
+            "{spinner} {msg} [{elapsed_precise}] {bytes} ({bytes_per_sec})",
+        )?);
+        return Ok(bar);
+    }
+    let bar = ProgressBar::new(fs::metadata(input)?.len()).with_message(msg);
+    bar.set_style(ProgressStyle::with_template(
+        "{msg} [{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+    )?);
+    Ok(bar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write_rows(format: OutputFormat, rows: &[Value]) -> Result<String> {
+        let mut buf = Vec::new();
+        let mut writer = row_writer(format, &mut buf, &CsvConvertOpts::default());
+        for row in rows {
+            writer.write_row(row)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_json_array_writer_matches_pretty_output() -> Result<()> {
+        let rows = vec![json!({"a": 1, "b": ["x"]}), json!({"a": 2, "b": []})];
+        assert_eq!(
+            write_rows(OutputFormat::Json, &rows)?,
+            serde_json::to_string_pretty(&rows)?
+        );
+        assert_eq!(write_rows(OutputFormat::Json, &[])?, "[]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ndjson_and_yaml_writers() -> Result<()> {
+        let rows = vec![json!({"a": 1}), json!({"a": null})];
+        assert_eq!(
+            write_rows(OutputFormat::Ndjson, &rows)?,
+            "{\"a\":1}\n{\"a\":null}\n"
+        );
+        assert_eq!(
+            write_rows(OutputFormat::Yaml, &rows)?,
+            serde_yaml::to_string(&rows)?
+        );
+        assert_eq!(write_rows(OutputFormat::Yaml, &[])?, "[]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_writers() -> Result<()> {
+        let rows = vec![json!({"a": 1}), json!({"a": "x"})];
+        let mut buf = Vec::new();
+        let mut writer = row_writer(OutputFormat::Cbor, &mut buf, &CsvConvertOpts::default());
+        for row in &rows {
+            writer.write_row(row)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        let ret: Vec<Value> = ciborium::from_reader(buf.as_slice())?;
+        assert_eq!(ret, rows);
+
+        let mut buf = Vec::new();
+        let mut writer = row_writer(OutputFormat::Msgpack, &mut buf, &CsvConvertOpts::default());
+        for row in &rows {
+            writer.write_row(row)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        assert_eq!(buf, rmp_serde::to_vec_named(&rows)?);
+        assert_eq!(msgpack_array_header(16)?, vec![0xdc, 0, 16]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_table_writer() -> Result<()> {
+        let rows = vec![json!({"a": 1}), json!({"a": null})];
+        assert_eq!(
+            write_rows(OutputFormat::Toml, &rows)?,
+            "[[rows]]\na = 1\n\n[[rows]]\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::Result;
+use console::{measure_text_width, pad_str, style, truncate_str, Alignment, Term};
+use serde_json::Value;
+use std::io::{self, Write};
+
+use super::{
+    csv_convert::{convert_rows, should_stream},
+    csv_stream::stream_rows,
+};
+use crate::{
+    cli::{ColorMode, InferMode},
+    CsvConvertOpts, CsvReaderOpts, RowWriter,
+};
+
+// columns are never squeezed below this to fit the terminal
+const MIN_WIDTH: usize = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TableOpts {
+    // rows shown, reading stops once one more is seen unless column inference needs all of stdin
+    pub limit: usize,
+    // cells wider than this are cut with an ellipsis
+    pub max_width: usize,
+    pub color: ColorMode,
+}
+
+impl Default for TableOpts {
+    fn default() -> Self {
+        Self {
+            limit: 20,
+            max_width: 30,
+            color: ColorMode::default(),
+        }
+    }
+}
+
+/// Collects the first rows and prints them as an aligned table on `finish`.
+pub struct TableWriter<W: Write> {
+    writer: W,
+    opts: TableOpts,
+    rows: Vec<Value>,
+    truncated: bool,
+}
+
+impl<W: Write> TableWriter<W> {
+    pub fn new(writer: W, opts: TableOpts) -> Self {
+        Self {
+            writer,
+            opts,
+            rows: Vec::new(),
+            truncated: false,
+        }
+    }
+}
+
+impl<W: Write> RowWriter for TableWriter<W> {
+    fn write_row(&mut self, row: &Value) -> Result<()> {
+        if self.rows.len() < self.opts.limit {
+            self.rows.push(row.clone());
+        } else {
+            self.truncated = true;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // only shrink to the terminal when printing to one
+        let term = Term::stdout();
+        let term_width = term
+            .is_term()
+            .then(|| term.size_checked())
+            .flatten()
+            .map(|(_, cols)| cols as usize);
+        let table = render_table(&self.rows, self.truncated, &self.opts, term_width);
+        self.writer.write_all(table.as_bytes())?;
+        Ok(self.writer.flush()?)
+    }
+
+    fn done(&self) -> bool {
+        self.truncated
+    }
+}
+
+/// Print the first `table_opts.limit` rows of `input` as a table on stdout.
+pub fn process_csv_show(
+    input: &str,
+    opts: &CsvReaderOpts,
+    convert_opts: &CsvConvertOpts,
+    table_opts: TableOpts,
+) -> Result<()> {
+    let make_writer = || {
+        let writer: Box<dyn RowWriter> = Box::new(TableWriter::new(io::stdout(), table_opts));
+        Ok(writer)
+    };
+    // streaming stops reading once the table is full; column inference of a file scans it first
+    // without keeping rows, only stdin has to be held in memory for it
+    let column_stdin = input == "-" && convert_opts.infer == InferMode::Column;
+    if !column_stdin || should_stream(input, convert_opts)? {
+        // a progress bar would end up below the table
+        stream_rows(input, make_writer, opts, convert_opts, false)
+    } else {
+        convert_rows(input, make_writer, opts, convert_opts)
+    }
+}
+
+/// Render `rows` with one column per key; numeric columns are right aligned.
+pub fn render_table(
+    rows: &[Value],
+    truncated: bool,
+    opts: &TableOpts,
+    term_width: Option<usize>,
+) -> String {
+    let columns = table_columns(rows);
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| columns.iter().map(|h| line_text(&row[*h])).collect())
+        .collect();
+    let numeric = numeric_columns(rows, &columns);
+    let headers: Vec<String> = columns
+        .iter()
+        .map(|h| line_text(&Value::from(*h)))
+        .collect();
+
+    let mut widths = column_widths(&headers, &cells);
+    for w in &mut widths {
+        *w = (*w).min(opts.max_width.max(1));
+    }
+    if let Some(term_width) = term_width {
+        fit_widths(&mut widths, term_width);
+    }
+
+    let line = |cells: &[String]| table_line(cells, &widths, &numeric);
+    let header = line(&headers);
+    let mut ret = match opts.color {
+        ColorMode::Auto => style(header).bold().cyan().to_string(),
+        ColorMode::Always => style(header).bold().cyan().force_styling(true).to_string(),
+        ColorMode::Never => header,
+    };
+    ret.push('\n');
+    let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    ret.push_str(&line(&rule));
+    ret.push('\n');
+    for row in &cells {
+        ret.push_str(&line(row));
+        ret.push('\n');
+    }
+    let plural = if rows.len() == 1 { "" } else { "s" };
+    if truncated {
+        ret.push_str(&format!("(first {} row{})\n", rows.len(), plural));
+    } else {
+        ret.push_str(&format!("({} row{})\n", rows.len(), plural));
+    }
+    ret
+}
+
+// the display width of each column, its widest cell or header
+pub(crate) fn column_widths(headers: &[String], cells: &[Vec<String>]) -> Vec<usize> {
+    headers
+        .iter()
+        .enumerate()
+        .map(|(i, h)| {
+            cells
+                .iter()
+                .map(|row| measure_text_width(&row[i]))
+                .fold(measure_text_width(h), usize::max)
+        })
+        .collect()
+}
+
+// cells padded to their column widths, two spaces apart; wider cells are truncated
+pub(crate) fn table_line(cells: &[String], widths: &[usize], numeric: &[bool]) -> String {
+    let padded: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .zip(numeric)
+        .map(|((cell, w), numeric)| {
+            let align = if *numeric {
+                Alignment::Right
+            } else {
+                Alignment::Left
+            };
+            // pad_str also truncates cells that fit exactly
+            if measure_text_width(cell) > *w {
+                truncate_str(cell, *w, "…").into_owned()
+            } else {
+                pad_str(cell, *w, align, None).into_owned()
+            }
+        })
+        .collect();
+    padded.join("  ").trim_end().to_string()
+}
+
+// every key of `rows`, in order of first appearance
+pub(crate) fn table_columns(rows: &[Value]) -> Vec<&str> {
+    let mut headers: Vec<&str> = Vec::new();
+    for row in rows {
+        for key in row.as_object().into_iter().flat_map(|map| map.keys()) {
+            if !headers.contains(&key.as_str()) {
+                headers.push(key);
+            }
+        }
+    }
+    headers
+}
+
+// columns with at least one value where every value is a number
+pub(crate) fn numeric_columns(rows: &[Value], headers: &[&str]) -> Vec<bool> {
+    headers
+        .iter()
+        .map(|h| {
+            let mut values = rows
+                .iter()
+                .map(|row| &row[*h])
+                .filter(|v| !v.is_null())
+                .peekable();
+            values.peek().is_some() && values.all(Value::is_number)
+        })
+        .collect()
+}
+
+// narrow the widest columns until the table, with its two space gaps, fits
+fn fit_widths(widths: &mut [usize], term_width: usize) {
+    let gaps = widths.len().saturating_sub(1) * 2;
+    while widths.iter().sum::<usize>() + gaps > term_width {
+        let Some(widest) = widths.iter_mut().filter(|w| **w > MIN_WIDTH).max() else {
+            break;
+        };
+        *widest -= 1;
+    }
+}
+
+// strings without quotes, null as an empty cell
+pub(crate) fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+// one line per row, so line breaks and tabs become spaces
+pub(crate) fn line_text(value: &Value) -> String {
+    cell_text(value).replace(['\r', '\n', '\t'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_table() {
+        let rows = vec![
+            json!({"name": "Gianluigi Buffon", "age": 40, "note": "line\nbreak"}),
+            json!({"name": "Dybala", "age": 9, "note": null}),
+        ];
+        let opts = TableOpts {
+            max_width: 10,
+            color: ColorMode::Never,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_table(&rows, true, &opts, None),
+            "name        age  note\n\
+             ----------  ---  ----------\n\
+             Gianluigi…   40  line break\n\
+             Dybala        9\n\
+             (first 2 rows)\n"
+        );
+        assert_eq!(
+            render_table(&rows[..1], false, &opts, Some(16)),
+            "name   age  note\n\
+             -----  ---  ----\n\
+             Gian…   40  lin…\n\
+             (1 row)\n"
+        );
+    }
+
+    #[test]
+    fn test_show_stops_reading() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        // the malformed last row is never read
+        std::fs::write(&input, "a,b\n1,2\n3,4\n5\n")?;
+        let convert_opts = CsvConvertOpts {
+            infer: InferMode::Cell,
+            ..Default::default()
+        };
+        let table_opts = TableOpts {
+            limit: 1,
+            color: ColorMode::Never,
+            ..Default::default()
+        };
+        let input = input.to_str().unwrap();
+        process_csv_show(input, &CsvReaderOpts::default(), &convert_opts, table_opts)
+    }
+}
@@ -0,0 +1,346 @@
+use anyhow::{anyhow, bail, Result};
+use csv::{Reader, StringRecord};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, fmt, io::Read};
+
+use super::csv_convert::read_headers;
+use crate::{get_content, BadRow, BadRowPolicy, ColumnType, CsvReaderOpts, RecordReader};
+
+/// Rules every record is checked against, keyed by column name.
+#[derive(Debug, Default)]
+pub struct ValidationSpec {
+    pub columns: HashMap<String, ColumnRule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ColumnRule {
+    pub ty: Option<ColumnType>,
+    // the column must be present in the header
+    pub required: bool,
+    // empty values are allowed
+    pub nullable: bool,
+    pub pattern: Option<Regex>,
+    pub values: Option<Vec<String>>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// A single failed check; `column` is 1-based and missing for header level problems.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub line: u64,
+    pub column: Option<usize>,
+    pub name: String,
+    pub message: String,
+}
+
+// the column spec format, `columns: {name: {type, required, nullable, pattern, enum, min, max}}`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSpec {
+    columns: HashMap<String, RawRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    #[serde(rename = "type")]
+    ty: Option<ColumnType>,
+    #[serde(default)]
+    required: bool,
+    #[serde(default = "default_nullable")]
+    nullable: bool,
+    pattern: Option<String>,
+    #[serde(rename = "enum")]
+    values: Option<Vec<Value>>,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl ColumnRule {
+    fn check(&self, value: &str) -> Option<String> {
+        if value.is_empty() {
+            return (!self.nullable).then(|| "value is required".to_string());
+        }
+        if let Some(ty) = self.ty {
+            if ty.convert(value).is_err() {
+                return Some(format!("{:?} is not a valid {}", value, ty));
+            }
+        }
+        if let Some(values) = &self.values {
+            if !values.iter().any(|v| v == value) {
+                return Some(format!("{:?} is not one of {}", value, values.join(", ")));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(value) {
+                return Some(format!("{:?} does not match /{}/", value, pattern));
+            }
+        }
+        if self.min.is_some() || self.max.is_some() {
+            let Ok(n) = value.trim().parse::<f64>() else {
+                return Some(format!("{:?} is not a number", value));
+            };
+            if self.min.is_some_and(|min| n < min) || self.max.is_some_and(|max| n > max) {
+                let bound = |b: Option<f64>| b.map_or_else(|| "..".to_string(), |b| b.to_string());
+                return Some(format!(
+                    "{} is out of range [{}, {}]",
+                    value,
+                    bound(self.min),
+                    bound(self.max)
+                ));
+            }
+        }
+        None
+    }
+}
+
+impl TryFrom<RawRule> for ColumnRule {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawRule) -> Result<Self> {
+        Ok(Self {
+            ty: raw.ty,
+            required: raw.required,
+            nullable: raw.nullable,
+            pattern: raw.pattern.as_deref().map(Regex::new).transpose()?,
+            values: raw
+                .values
+                .map(|values| values.iter().map(value_to_string).collect()),
+            min: raw.min,
+            max: raw.max,
+        })
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(
+                f,
+                "line {}, column {} ({}): {}",
+                self.line, column, self.name, self.message
+            ),
+            None => write!(f, "line {} ({}): {}", self.line, self.name, self.message),
+        }
+    }
+}
+
+/// Load a column spec or a JSON Schema (anything with `properties`) from a YAML or JSON file.
+pub fn load_validation_spec(path: &str) -> Result<ValidationSpec> {
+    let content = get_content(path)?;
+    parse_spec(serde_yaml::from_slice(&content)?)
+}
+
+fn parse_spec(value: Value) -> Result<ValidationSpec> {
+    if value.get("properties").is_some() {
+        return from_json_schema(&value);
+    }
+    let raw: RawSpec = serde_json::from_value(value)?;
+    let columns = raw
+        .columns
+        .into_iter()
+        .map(|(name, rule)| {
+            let rule =
+                ColumnRule::try_from(rule).map_err(|e| anyhow!("column {:?}: {}", name, e))?;
+            Ok((name, rule))
+        })
+        .collect::<Result<_>>()?;
+    Ok(ValidationSpec { columns })
+}
+
+// the JSON Schema subset that maps onto CSV cells: scalar types, pattern, enum and ranges
+fn from_json_schema(schema: &Value) -> Result<ValidationSpec> {
+    let Some(properties) = schema["properties"].as_object() else {
+        bail!("JSON Schema properties must be an object");
+    };
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let mut columns = HashMap::new();
+    for (name, prop) in properties {
+        let types: Vec<&str> = match &prop["type"] {
+            Value::String(ty) => vec![ty.as_str()],
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        let ty = match types.iter().find(|t| **t != "null") {
+            Some(t) => Some(
+                json_schema_type(t, prop["format"].as_str())
+                    .ok_or_else(|| anyhow!("column {:?}: unsupported type {}", name, t))?,
+            ),
+            None => None,
+        };
+        let rule = ColumnRule {
+            ty,
+            required: required.contains(&name.as_str()),
+            nullable: types.is_empty() || types.contains(&"null"),
+            pattern: prop["pattern"].as_str().map(Regex::new).transpose()?,
+            values: prop["enum"]
+                .as_array()
+                .map(|values| values.iter().map(value_to_string).collect()),
+            min: prop["minimum"].as_f64(),
+            max: prop["maximum"].as_f64(),
+        };
+        columns.insert(name.clone(), rule);
+    }
+    Ok(ValidationSpec { columns })
+}
+
+fn json_schema_type(ty: &str, format: Option<&str>) -> Option<ColumnType> {
+    match (ty, format) {
+        ("integer", _) => Some(ColumnType::Integer),
+        ("number", _) => Some(ColumnType::Float),
+        ("boolean", _) => Some(ColumnType::Boolean),
+        ("string", Some("date")) => Some(ColumnType::Date),
+        ("string", Some("date-time")) => Some(ColumnType::Datetime),
+        ("string", _) => Some(ColumnType::String),
+        _ => None,
+    }
+}
+
+/// Check every record in `reader` against `spec`, collecting all violations; malformed records
+/// are violations too.
+pub fn process_csv_validate<R: Read>(
+    mut reader: Reader<R>,
+    opts: &CsvReaderOpts,
+    spec: &ValidationSpec,
+) -> Result<Vec<Violation>> {
+    let headers = read_headers(&mut reader, opts)?;
+    let names: Vec<String> = match &headers {
+        Some(headers) => headers.iter().map(String::from).collect(),
+        None => Vec::new(),
+    };
+    let mut ret = Vec::new();
+    let mut missing: Vec<&String> = spec
+        .columns
+        .iter()
+        .filter(|(name, rule)| rule.required && headers.is_some() && !names.contains(name))
+        .map(|(name, _)| name)
+        .collect();
+    missing.sort();
+    for name in missing {
+        ret.push(Violation {
+            line: 1,
+            column: None,
+            name: name.clone(),
+            message: "required column is missing".to_string(),
+        });
+    }
+
+    let mut records = RecordReader::new(reader, opts, &BadRowPolicy::Fail)?;
+    let mut record = StringRecord::new();
+    loop {
+        match records.read_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            // the reader moves past a bad row, so the rest of the file is still checked
+            Err(e) => {
+                let bad = e.downcast::<BadRow>()?;
+                ret.push(Violation {
+                    line: bad.line,
+                    column: None,
+                    name: "row".to_string(),
+                    message: bad.message,
+                });
+                continue;
+            }
+        }
+        let line = record.position().map_or(0, |p| p.line());
+        for (i, value) in record.iter().enumerate() {
+            let name = match names.get(i) {
+                Some(name) => name.clone(),
+                None => format!("col_{}", i + 1),
+            };
+            let Some(rule) = spec.columns.get(&name) else {
+                continue;
+            };
+            if let Some(message) = rule.check(value) {
+                ret.push(Violation {
+                    line,
+                    column: Some(i + 1),
+                    name,
+                    message,
+                });
+            }
+        }
+    }
+    Ok(ret)
+}
+
+fn default_nullable() -> bool {
+    true
+}
+
+fn value_to_string(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(spec: &str, data: &str) -> Result<Vec<String>> {
+        let spec = parse_spec(serde_yaml::from_str(spec)?)?;
+        let opts = CsvReaderOpts::default();
+        let violations = process_csv_validate(opts.from_reader(data.as_bytes()), &opts, &spec)?;
+        Ok(violations.iter().map(|v| v.to_string()).collect())
+    }
+
+    #[test]
+    fn test_validate_column_spec() -> Result<()> {
+        let spec = r#"
+columns:
+  id: {type: integer, nullable: false}
+  age: {type: integer, min: 0, max: 150}
+  status: {enum: [active, inactive]}
+  email: {pattern: "^[^@]+@[^@]+$", required: true}
+  team: {required: true}
+"#;
+        let data = "id,age,status,email\n1,30,active,a@b.c\n,200,gone,nope\nx,,active,c@d.e\n2,3\n\
+                    4,-1,active,f@g.h\n";
+        assert_eq!(
+            validate(spec, data)?,
+            vec![
+                "line 1 (team): required column is missing",
+                "line 3, column 1 (id): value is required",
+                "line 3, column 2 (age): 200 is out of range [0, 150]",
+                "line 3, column 3 (status): \"gone\" is not one of active, inactive",
+                "line 3, column 4 (email): \"nope\" does not match /^[^@]+@[^@]+$/",
+                "line 4, column 1 (id): \"x\" is not a valid integer",
+                "line 5 (row): expected 4 fields, found 2",
+                "line 6, column 2 (age): -1 is out of range [0, 150]",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_json_schema() -> Result<()> {
+        let spec = r#"{
+            "type": "object",
+            "required": ["id"],
+            "properties": {
+                "id": {"type": "integer", "minimum": 1},
+                "born": {"type": ["string", "null"], "format": "date"}
+            }
+        }"#;
+        let data = "id,born\n0,2020-01-01\n2,\n3,yesterday\n";
+        assert_eq!(
+            validate(spec, data)?,
+            vec![
+                "line 2, column 1 (id): 0 is out of range [1, ..]",
+                "line 4, column 2 (born): \"yesterday\" is not a valid date",
+            ]
+        );
+        Ok(())
+    }
+}
@@ -1,12 +1,65 @@
 mod b64;
+mod csv_bad_rows;
+mod csv_cat;
 mod csv_convert;
+mod csv_diff;
+mod csv_encoding;
+mod csv_filter;
+mod csv_from;
+mod csv_group;
+mod csv_infer;
+mod csv_join;
+mod csv_markup;
+mod csv_mask;
+mod csv_nest;
+mod csv_parallel;
+mod csv_parquet;
+mod csv_query;
+mod csv_select;
+mod csv_sheet;
+mod csv_sort;
+mod csv_split;
+mod csv_sql;
+mod csv_stats;
+mod csv_stream;
+mod csv_table;
+mod csv_validate;
 mod gen_pass;
 mod http_serve;
 mod jwt;
 mod text;
 
 pub use b64::{process_decode, process_encode};
-pub use csv_convert::process_csv;
+pub use csv_bad_rows::{BadRow, BadRowPolicy, RecordReader};
+pub use csv_cat::process_csv_cat;
+pub use csv_convert::{process_csv, CsvConvertOpts, CsvReaderOpts};
+pub use csv_diff::{
+    diff_to_json_patch, process_csv_diff, render_diff_report, CellChange, CsvDiff, RowChange,
+};
+pub use csv_encoding::{decode_reader, detect_encoding};
+pub use csv_filter::{CmpOp, Filter, Operand};
+pub use csv_from::process_csv_from;
+pub use csv_group::{Aggregate, Dedupe, DedupeWriter, GroupBy, GroupWriter};
+pub use csv_infer::{
+    detect_row_types, infer_row, infer_values, load_schema, ColumnType, CsvSchema,
+};
+pub use csv_join::{process_csv_join, JoinSpec};
+pub use csv_markup::{render_html, render_markdown, HtmlWriter, MarkdownWriter};
+pub use csv_mask::{FakeKind, MaskRule, MaskStrategy, MaskWriter};
+pub use csv_nest::{nest_row, NestWriter};
+pub use csv_parallel::{ParallelReader, RowMap, RowReader};
+pub use csv_query::process_csv_query;
+pub use csv_select::{ColumnSelection, ProjectWriter, Projection};
+pub use csv_sheet::sheet_reader;
+pub use csv_sort::{compare_rows, SortKey, SortWriter};
+pub use csv_split::{process_csv_split, SplitMode};
+pub use csv_sql::{SqlRowWriter, SqliteRowWriter};
+pub use csv_stats::{process_csv_stats, render_stats_table, ColumnStats, TopValue};
+pub use csv_stream::{row_writer, rows_writer, stream_csv, BatchSchema, RowWriter};
+pub use csv_table::{process_csv_show, render_table, TableOpts, TableWriter};
+pub use csv_validate::{
+    load_validation_spec, process_csv_validate, ColumnRule, ValidationSpec, Violation,
+};
 pub use gen_pass::process_gen_pass;
 pub use http_serve::process_http_serve;
 pub use jwt::{process_jwt_sign, process_jwt_verify};
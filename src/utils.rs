@@ -1,5 +1,12 @@
 use anyhow::Result;
-use std::{fs::File, io::Read};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    process,
+};
+
+// stdout for `-o -`, exiting quietly once the reader is gone, as in `rcli csv ... | head`
+struct StdoutWriter(io::Stdout);
 
 pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
     let reader: Box<dyn Read> = if input == "-" {
@@ -10,9 +17,35 @@ pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
     Ok(reader)
 }
 
+pub fn get_writer(output: &str) -> Result<Box<dyn Write + Send>> {
+    let writer: Box<dyn Write + Send> = if output == "-" {
+        Box::new(StdoutWriter(io::stdout()))
+    } else {
+        Box::new(File::create(output)?)
+    };
+    Ok(writer)
+}
+
 pub fn get_content(input: &str) -> Result<Vec<u8>> {
     let mut reader = get_reader(input)?;
     let mut buf = Vec::new();
     reader.read_to_end(&mut buf)?;
     Ok(buf)
 }
+
+impl Write for StdoutWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        exit_on_broken_pipe(self.0.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        exit_on_broken_pipe(self.0.flush())
+    }
+}
+
+fn exit_on_broken_pipe<T>(ret: io::Result<T>) -> io::Result<T> {
+    match ret {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => process::exit(0),
+        ret => ret,
+    }
+}